@@ -4,7 +4,7 @@ extern crate mio_byte_fifo;
 use std::io::{Read, Write, ErrorKind};
 use std::thread;
 
-use mio::{Poll, Events, Token, Ready, PollOpt};
+use mio::{Poll, Events, Token, Interest};
 
 use mio_byte_fifo::{Producer, Consumer};
 
@@ -14,13 +14,13 @@ fn main() {
     const READ_BUF_SIZE: usize = 7;
     const EVENTS_CAPACITY: usize = 4;
     
-    let (mut producer, mut consumer) = mio_byte_fifo::create(FIFO_SIZE);
+    let (mut producer, mut consumer) = mio_byte_fifo::create(FIFO_SIZE).unwrap();
     let message = "The quick brown fox jumps over the lazy dog";
 
     println!("sending message: '{}'", message);
 
     let producer_thread = thread::spawn(move || {
-        let poll = Poll::new().unwrap();
+        let mut poll = Poll::new().unwrap();
         let mut events = Events::with_capacity(EVENTS_CAPACITY);
         let data = message.as_bytes();
         let mut pos = 0;
@@ -46,7 +46,7 @@ fn main() {
             }
         };
 
-        poll.register(&producer, Token(0), Ready::writable(), PollOpt::edge()).unwrap();
+        poll.registry().register(&mut producer, Token(0), Interest::WRITABLE).unwrap();
         
         if !write_data_part(&mut producer, &mut pos) {
             return;
@@ -57,7 +57,7 @@ fn main() {
 
             for event in events.iter() {
                 assert_eq!(event.token(), Token(0));
-                assert!(event.readiness().is_writable());
+                assert!(event.is_writable());
 
                 if !write_data_part(&mut producer, &mut pos) {
                     break 'outer;
@@ -67,7 +67,7 @@ fn main() {
     });
 
     let consumer_thread = thread::spawn(move || {
-        let poll = Poll::new().unwrap();
+        let mut poll = Poll::new().unwrap();
         let mut events = Events::with_capacity(EVENTS_CAPACITY);
         let mut data = String::new();
         let mut buf = [0; READ_BUF_SIZE];
@@ -75,6 +75,7 @@ fn main() {
         let mut read_data_part = |consumer: &mut Consumer, data: &mut String| {
             loop {
                 match consumer.read(&mut buf) {
+                    Ok(0) => break false,
                     Ok(n) => {
                         let str_part = std::str::from_utf8(&buf[0..n]).unwrap();
                         println!("received {} bytes: '{}'", n, str_part);
@@ -82,7 +83,6 @@ fn main() {
                     },
                     Err(err) => {
                         match err.kind() {
-                            ErrorKind::BrokenPipe => break false,
                             ErrorKind::WouldBlock => break true,
                             _ => panic!("{:?}", err),
                         }
@@ -91,14 +91,14 @@ fn main() {
             }
         };
 
-        poll.register(&consumer, Token(0), Ready::readable(), PollOpt::edge()).unwrap();
+        poll.registry().register(&mut consumer, Token(0), Interest::READABLE).unwrap();
         
         'outer: loop {
             poll.poll(&mut events, None).unwrap();
 
             for event in events.iter() {
                 assert_eq!(event.token(), Token(0));
-                assert!(event.readiness().is_readable());
+                assert!(event.is_readable() || event.is_read_closed());
                 if !read_data_part(&mut consumer, &mut data) {
                     break 'outer;
                 }
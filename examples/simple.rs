@@ -4,7 +4,7 @@ use std::io::{Read, Write};
 
 
 fn main() {
-    let (mut producer, mut consumer) = mio_byte_fifo::create(16);
+    let (mut producer, mut consumer) = mio_byte_fifo::create(16).unwrap();
 
     let data = [0, 1, 254, 255];
     let n = producer.write(&data).unwrap();
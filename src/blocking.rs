@@ -0,0 +1,264 @@
+//! Blocking adapters for threads that don't run a [`Poll`](mio::Poll) loop.
+//!
+//! The other end may stay non-blocking, it wakes blocked threads on every change of the ring buffer.
+
+use std::io::{Write, Read, Error, ErrorKind};
+use std::time::{Duration, Instant};
+
+use crate::{Producer, Consumer};
+
+/// Producer that blocks until data can be written.
+#[derive(Debug)]
+pub struct BlockingProducer {
+    inner: Producer,
+    timeout: Option<Duration>,
+}
+
+/// Consumer that blocks until data can be read.
+#[derive(Debug)]
+pub struct BlockingConsumer {
+    inner: Consumer,
+    timeout: Option<Duration>,
+}
+
+/// Returns how long to wait until `deadline`, fails if it is already passed.
+fn remaining(deadline: Option<Instant>, msg: &'static str) -> Result<Option<Duration>, Error> {
+    match deadline {
+        Some(d) => match d.checked_duration_since(Instant::now()) {
+            Some(t) if t > Duration::from_secs(0) => Ok(Some(t)),
+            _ => Err(Error::new(ErrorKind::TimedOut, msg)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn check_timeout(timeout: Option<Duration>) -> Result<(), Error> {
+    if timeout == Some(Duration::from_secs(0)) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Cannot set a zero duration timeout",
+        ))
+    }
+    Ok(())
+}
+
+impl BlockingProducer {
+    pub fn new(inner: Producer) -> Self {
+        Self { inner, timeout: None }
+    }
+
+    /// Returns the timeout of a single write, `None` means waiting forever.
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the timeout of a single write, `None` means waiting forever.
+    ///
+    /// Writing fails with [`ErrorKind::TimedOut`] when it expires.
+    /// Zero duration is rejected the same way as [`std::net::TcpStream::set_write_timeout`] does.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        check_timeout(timeout)?;
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &Producer {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut Producer {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> Producer {
+        self.inner
+    }
+}
+
+impl BlockingConsumer {
+    pub fn new(inner: Consumer) -> Self {
+        Self { inner, timeout: None }
+    }
+
+    /// Returns the timeout of a single read, `None` means waiting forever.
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the timeout of a single read, `None` means waiting forever.
+    ///
+    /// Reading fails with [`ErrorKind::TimedOut`] when it expires.
+    /// Zero duration is rejected the same way as [`std::net::TcpStream::set_read_timeout`] does.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error> {
+        check_timeout(timeout)?;
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &Consumer {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut Consumer {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> Consumer {
+        self.inner
+    }
+}
+
+impl Write for BlockingProducer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let deadline = self.timeout.map(|t| Instant::now() + t);
+        loop {
+            let seq = self.inner.rdw.progress_seq();
+            match self.inner.write(buf) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    let timeout = remaining(deadline, "Write timed out")?;
+                    self.inner.rdw.wait(seq, timeout);
+                },
+                res => break res,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
+
+impl Read for BlockingConsumer {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let deadline = self.timeout.map(|t| Instant::now() + t);
+        loop {
+            let seq = self.inner.rdr.progress_seq();
+            match self.inner.read(buf) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    let timeout = remaining(deadline, "Read timed out")?;
+                    self.inner.rdr.wait(seq, timeout);
+                },
+                res => break res,
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::thread;
+
+    use crate::{create, create_with, Watermarks};
+
+    #[test]
+    fn read_block() {
+        let (mut p, c) = create(4).unwrap();
+        let mut c = c.into_blocking();
+        let mut buf = [0; 4];
+
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(p.write(b"abc").unwrap(), 3);
+        });
+
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[0..3], b"abc");
+
+        jh.join().unwrap();
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn write_block() {
+        let (p, mut c) = create(4).unwrap();
+        let mut p = p.into_blocking();
+        let mut buf = [0; 4];
+
+        assert_eq!(p.write(b"abcd").unwrap(), 4);
+
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(c.read(&mut buf[0..2]).unwrap(), 2);
+            c
+        });
+
+        assert_eq!(p.write(b"efg").unwrap(), 2);
+
+        drop(jh.join().unwrap());
+        assert_eq!(p.write(b"g").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn watermarks() {
+        let (mut p, c) = create_with(16, Watermarks { low: 4, high: 16 }).unwrap();
+        let mut c = c.into_blocking();
+
+        // The read returns before `low` bytes are buffered.
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(p.write(b"ab").unwrap(), 2);
+            p
+        });
+        assert_eq!(c.read(&mut [0; 4]).unwrap(), 2);
+        let mut p = jh.join().unwrap().into_blocking();
+
+        // The write returns before `high` bytes are freed.
+        assert_eq!(p.write(&[0; 16]).unwrap(), 16);
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(c.read(&mut [0; 2]).unwrap(), 2);
+            c
+        });
+        assert_eq!(p.write(b"cdef").unwrap(), 2);
+        let _c = jh.join().unwrap();
+    }
+
+    #[test]
+    fn timeouts() {
+        let (p, c) = create(4).unwrap();
+        let (mut p, mut c) = (p.into_blocking(), c.into_blocking());
+
+        assert_eq!(p.set_write_timeout(Some(Duration::from_secs(0))).unwrap_err().kind(), ErrorKind::InvalidInput);
+        p.set_write_timeout(Some(Duration::from_millis(10))).unwrap();
+        c.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+        assert_eq!(c.read_timeout(), Some(Duration::from_millis(10)));
+
+        let start = Instant::now();
+        let err = c.read(&mut [0; 4]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert_eq!(err.to_string(), "Read timed out");
+        assert!(start.elapsed() >= Duration::from_millis(10));
+
+        assert_eq!(p.write(b"abcd").unwrap(), 4);
+        let err = p.write(b"e").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert_eq!(err.to_string(), "Write timed out");
+    }
+
+    #[test]
+    fn copy() {
+        let (p, c) = create(7).unwrap();
+        let (mut p, mut c) = (p.into_blocking(), c.into_blocking());
+        let data = (0..1000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let sent = data.clone();
+
+        let jh = thread::spawn(move || {
+            p.write_all(&sent).unwrap();
+        });
+
+        let mut received = Vec::new();
+        c.read_to_end(&mut received).unwrap();
+        assert_eq!(received, data);
+
+        jh.join().unwrap();
+    }
+}
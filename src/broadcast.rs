@@ -0,0 +1,516 @@
+//! Broadcast FIFO, every consumer gets all the data written by the producer.
+//!
+//! Unlike the SPSC FIFO, the state is kept under a mutex,
+//! so readiness of all ends is updated together with the cursors.
+
+use std::io::{Write, Read, Error, ErrorKind};
+use std::fmt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use mio::{Registry, Token, Interest, event::Source, unix::SourceFd};
+
+use crate::signal::{self, PipeReader, PipeWriter};
+
+/// What the producer does when the slowest consumer has no free space left.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Writing fails with [`ErrorKind::WouldBlock`] until the slowest consumer reads.
+    #[default]
+    Block,
+    /// The oldest data is dropped for consumers that lag behind, writing never blocks.
+    Overwrite,
+}
+
+/// Cursor and readiness of a single consumer.
+struct Slot {
+    cursor: u64,
+    lost: u64,
+    reg: PipeReader,
+    src: Option<PipeWriter>,
+    readable: bool,
+}
+
+struct Shared {
+    data: Box<[u8]>,
+    head: u64,
+    policy: LagPolicy,
+    slots: Vec<Option<Slot>>,
+    reg: PipeWriter,
+    srp: Option<PipeReader>,
+    writable: bool,
+    closed: bool,
+}
+
+/// Producer of a broadcast FIFO.
+pub struct BroadcastProducer {
+    shared: Arc<Mutex<Shared>>,
+    fd: RawFd,
+}
+
+/// Consumer of a broadcast FIFO, each clone has its own cursor and readiness.
+pub struct BroadcastConsumer {
+    shared: Arc<Mutex<Shared>>,
+    id: usize,
+    fd: RawFd,
+}
+
+/// Creates a broadcast FIFO with a single consumer, more of them are made by cloning it.
+///
+/// Each consumer can lag behind the producer by up to `capacity` bytes.
+pub fn create_broadcast(capacity: usize, policy: LagPolicy) -> Result<(BroadcastProducer, BroadcastConsumer), Error> {
+    if capacity == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Capacity must be non-zero",
+        ))
+    }
+    let (srp, reg) = signal::pipe()?;
+    let fd = reg.as_raw_fd();
+    let shared = Arc::new(Mutex::new(Shared {
+        data: vec![0; capacity].into_boxed_slice(),
+        head: 0,
+        policy,
+        slots: Vec::new(),
+        reg,
+        srp: Some(srp),
+        writable: true,
+        closed: false,
+    }));
+    let cons = Shared::subscribe(&shared, None)?;
+    Ok((BroadcastProducer { shared, fd }, cons))
+}
+
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Shared {
+    /// Adds a consumer starting from the cursor of consumer `from`, or from the head if there is none.
+    fn subscribe(this: &Arc<Mutex<Self>>, from: Option<usize>) -> Result<BroadcastConsumer, Error> {
+        let (reg, src) = signal::pipe()?;
+        let fd = reg.as_raw_fd();
+        let mut sh = lock(this);
+        let cursor = from.map_or(sh.head, |id| sh.slots[id].as_ref().unwrap().cursor);
+        let src = if sh.closed { None } else { Some(src) };
+        let slot = Slot { cursor, lost: 0, reg, src, readable: false };
+        let id = match sh.slots.iter().position(Option::is_none) {
+            Some(id) => { sh.slots[id] = Some(slot); id },
+            None => { sh.slots.push(Some(slot)); sh.slots.len() - 1 },
+        };
+        if let Err(err) = sh.update() {
+            // The consumer is never returned, so its slot must not hold the producer back.
+            sh.slots[id] = None;
+            return Err(err);
+        }
+        Ok(BroadcastConsumer { shared: this.clone(), id, fd })
+    }
+
+    fn capacity(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// Returns the cursor of the slowest consumer.
+    fn tail(&self) -> u64 {
+        self.slots.iter().flatten().map(|s| s.cursor).min().unwrap_or(self.head)
+    }
+
+    /// Returns the number of bytes the producer can write.
+    fn free(&self) -> u64 {
+        match self.policy {
+            LagPolicy::Block => self.capacity() - (self.head - self.tail()),
+            LagPolicy::Overwrite => self.capacity(),
+        }
+    }
+
+    /// Brings pipes in line with the cursors.
+    fn update(&mut self) -> Result<(), Error> {
+        let writable = self.free() > 0;
+        if writable != self.writable {
+            if writable {
+                if let Some(srp) = &self.srp {
+                    srp.drain()?;
+                }
+            } else {
+                self.reg.fill()?;
+            }
+            self.writable = writable;
+        }
+        let head = self.head;
+        for slot in self.slots.iter_mut().flatten() {
+            let readable = slot.cursor < head;
+            if readable != slot.readable {
+                if readable {
+                    if let Some(src) = &slot.src {
+                        src.notify()?;
+                    }
+                } else {
+                    slot.reg.drain()?;
+                }
+                slot.readable = readable;
+            }
+        }
+        Ok(())
+    }
+
+    /// Position of `pos` in the data buffer.
+    fn index(&self, pos: u64) -> usize {
+        (pos % self.capacity()) as usize
+    }
+
+    /// Copies `src` to the data buffer starting from `pos`, wrapping around its end.
+    fn copy_in(&mut self, pos: u64, src: &[u8]) {
+        let start = self.index(pos);
+        let first = src.len().min(self.data.len() - start);
+        self.data[start..start + first].copy_from_slice(&src[..first]);
+        self.data[..src.len() - first].copy_from_slice(&src[first..]);
+    }
+
+    /// Copies data starting from `pos` to `dst`, wrapping around the end of the data buffer.
+    fn copy_out(&self, pos: u64, dst: &mut [u8]) {
+        let start = self.index(pos);
+        let first = dst.len().min(self.data.len() - start);
+        let (left, right) = dst.split_at_mut(first);
+        left.copy_from_slice(&self.data[start..start + first]);
+        right.copy_from_slice(&self.data[..right.len()]);
+    }
+}
+
+impl BroadcastProducer {
+    /// Returns the number of consumers.
+    pub fn receiver_count(&self) -> usize {
+        lock(&self.shared).slots.iter().flatten().count()
+    }
+}
+
+impl BroadcastConsumer {
+    /// Returns the number of bytes dropped for this consumer because it lagged behind.
+    ///
+    /// Always zero with [`LagPolicy::Block`].
+    pub fn lost_bytes(&self) -> u64 {
+        lock(&self.shared).slots[self.id].as_ref().unwrap().lost
+    }
+
+    /// Creates another consumer that starts from the current position of this one.
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Shared::subscribe(&self.shared, Some(self.id))
+    }
+}
+
+impl Clone for BroadcastConsumer {
+    /// # Panics
+    ///
+    /// Panics if a readiness pipe cannot be created, see [`try_clone`](Self::try_clone).
+    fn clone(&self) -> Self {
+        self.try_clone().expect("Cannot clone consumer")
+    }
+}
+
+impl fmt::Debug for BroadcastProducer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sh = lock(&self.shared);
+        f.debug_struct("BroadcastProducer")
+            .field("head", &sh.head)
+            .field("policy", &sh.policy)
+            .finish()
+    }
+}
+
+impl fmt::Debug for BroadcastConsumer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sh = lock(&self.shared);
+        f.debug_struct("BroadcastConsumer")
+            .field("cursor", &sh.slots[self.id].as_ref().unwrap().cursor)
+            .field("policy", &sh.policy)
+            .finish()
+    }
+}
+
+impl Source for BroadcastProducer {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        SourceFd(&self.fd).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        SourceFd(&self.fd).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> Result<(), Error> {
+        SourceFd(&self.fd).deregister(registry)
+    }
+}
+
+impl Source for BroadcastConsumer {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        SourceFd(&self.fd).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        SourceFd(&self.fd).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> Result<(), Error> {
+        SourceFd(&self.fd).deregister(registry)
+    }
+}
+
+impl Drop for BroadcastProducer {
+    fn drop(&mut self) {
+        let mut sh = lock(&self.shared);
+        sh.closed = true;
+        // Closing write ends hangs up the pipes registered by the consumers.
+        for slot in sh.slots.iter_mut().flatten() {
+            slot.src = None;
+        }
+    }
+}
+
+impl Drop for BroadcastConsumer {
+    fn drop(&mut self) {
+        let mut sh = lock(&self.shared);
+        sh.slots[self.id] = None;
+        if sh.slots.iter().all(Option::is_none) {
+            // The last consumer makes the pipe registered by the producer report an error.
+            sh.srp = None;
+        } else {
+            // There is no way to report an error from here.
+            let _ = sh.update();
+        }
+    }
+}
+
+impl Write for BroadcastProducer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let mut sh = lock(&self.shared);
+        if sh.srp.is_none() {
+            return Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "Consumer was closed",
+            ))
+        }
+        let num = (sh.free() as usize).min(buf.len());
+        if num == 0 && !buf.is_empty() {
+            return Err(Error::new(
+                ErrorKind::WouldBlock,
+                "Ring buffer is full",
+            ))
+        }
+        let head = sh.head;
+        sh.copy_in(head, &buf[..num]);
+        sh.head += num as u64;
+        let (head, cap) = (sh.head, sh.capacity());
+        for slot in sh.slots.iter_mut().flatten() {
+            if head - slot.cursor > cap {
+                slot.lost += head - cap - slot.cursor;
+                slot.cursor = head - cap;
+            }
+        }
+        sh.update().and(Ok(num))
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Read for BroadcastConsumer {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut sh = lock(&self.shared);
+        let cursor = sh.slots[self.id].as_ref().unwrap().cursor;
+        let num = ((sh.head - cursor) as usize).min(buf.len());
+        if num == 0 && !buf.is_empty() {
+            return if sh.closed {
+                Ok(0)
+            } else {
+                Err(Error::new(
+                    ErrorKind::WouldBlock,
+                    "Ring buffer is empty",
+                ))
+            }
+        }
+        sh.copy_out(cursor, &mut buf[..num]);
+        sh.slots[self.id].as_mut().unwrap().cursor += num as u64;
+        sh.update().and(Ok(num))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::thread;
+    use std::time::Duration;
+
+    use mio::{Poll, Events};
+
+    #[test]
+    fn fan_out() {
+        let (mut p, mut c) = create_broadcast(8, LagPolicy::Block).unwrap();
+        let mut buf = [0; 8];
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        let mut d = c.clone();
+        assert_eq!(p.receiver_count(), 2);
+
+        assert_eq!(c.read(&mut buf[0..2]).unwrap(), 2);
+        assert_eq!(&buf[0..2], b"ab");
+        let mut e = c.clone();
+
+        assert_eq!(p.write(b"def").unwrap(), 3);
+        assert_eq!(d.read(&mut buf).unwrap(), 6);
+        assert_eq!(&buf[0..6], b"abcdef");
+        assert_eq!(c.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[0..4], b"cdef");
+        assert_eq!(e.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[0..4], b"cdef");
+        assert_eq!(e.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn block() {
+        let (mut p, mut c) = create_broadcast(4, LagPolicy::Block).unwrap();
+        let mut d = c.clone();
+        let mut buf = [0; 4];
+
+        assert_eq!(p.write(b"abcde").unwrap(), 4);
+        assert_eq!(c.read(&mut buf).unwrap(), 4);
+        assert_eq!(p.write(b"e").unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(d.read(&mut buf[0..1]).unwrap(), 1);
+        assert_eq!(p.write(b"ef").unwrap(), 1);
+
+        drop(d);
+        assert_eq!(p.write(b"fghi").unwrap(), 3);
+        assert_eq!(p.write(b"i").unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(c.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"efgh");
+    }
+
+    #[test]
+    fn overwrite() {
+        let (mut p, mut c) = create_broadcast(4, LagPolicy::Overwrite).unwrap();
+        let mut d = c.clone();
+        let mut buf = [0; 4];
+
+        assert_eq!(p.write(b"abcd").unwrap(), 4);
+        assert_eq!(c.read(&mut buf).unwrap(), 4);
+        assert_eq!(p.write(b"efg").unwrap(), 3);
+
+        assert_eq!(d.lost_bytes(), 3);
+        assert_eq!(d.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"defg");
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[0..3], b"efg");
+        assert_eq!(c.lost_bytes(), 0);
+    }
+
+    #[test]
+    fn close() {
+        let (mut p, mut c) = create_broadcast(4, LagPolicy::Block).unwrap();
+        let d = c.clone();
+        let mut buf = [0; 4];
+
+        assert_eq!(p.write(b"ab").unwrap(), 2);
+        drop(p);
+        assert_eq!(c.read(&mut buf).unwrap(), 2);
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+        let mut e = d.clone();
+        assert_eq!(e.read(&mut buf).unwrap(), 2);
+        assert_eq!(e.read(&mut buf).unwrap(), 0);
+
+        let (mut p, c) = create_broadcast(4, LagPolicy::Block).unwrap();
+        let d = c.clone();
+        drop(c);
+        assert_eq!(p.write(b"ab").unwrap(), 2);
+        drop(d);
+        let err = p.write(b"ab").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        assert_eq!(err.to_string(), "Consumer was closed");
+    }
+
+    #[test]
+    fn poll() {
+        let (mut p, mut c) = create_broadcast(4, LagPolicy::Block).unwrap();
+        let mut d = c.clone();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let timeout = Some(Duration::from_millis(100));
+
+        poll.registry().register(&mut c, Token(1), Interest::READABLE).unwrap();
+        poll.registry().register(&mut d, Token(2), Interest::READABLE).unwrap();
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        poll.poll(&mut events, timeout).unwrap();
+        assert_eq!(events.iter().map(|e| e.token()).collect::<Vec<_>>(), [Token(0)]);
+
+        assert_eq!(p.write(b"abcd").unwrap(), 4);
+        poll.poll(&mut events, timeout).unwrap();
+        let mut tokens = events.iter().map(|e| e.token()).collect::<Vec<_>>();
+        tokens.sort();
+        assert_eq!(tokens, [Token(1), Token(2)]);
+
+        assert_eq!(c.read(&mut [0; 4]).unwrap(), 4);
+        poll.poll(&mut events, Some(Duration::from_millis(10))).unwrap();
+        assert!(events.is_empty());
+
+        assert_eq!(d.read(&mut [0; 2]).unwrap(), 2);
+        poll.poll(&mut events, timeout).unwrap();
+        assert_eq!(events.iter().map(|e| e.token()).collect::<Vec<_>>(), [Token(0)]);
+
+        drop(p);
+        poll.poll(&mut events, timeout).unwrap();
+        let mut tokens = events.iter().filter(|e| e.is_read_closed()).map(|e| e.token()).collect::<Vec<_>>();
+        tokens.sort();
+        assert_eq!(tokens, [Token(1), Token(2)]);
+    }
+
+    #[test]
+    fn threads() {
+        const SIZE: usize = 1000;
+        let (p, c) = create_broadcast(7, LagPolicy::Block).unwrap();
+        let data = (0..SIZE).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+        let jhs = (0..3).map(|_| {
+            let mut c = c.clone();
+            thread::spawn(move || {
+                let mut poll = Poll::new().unwrap();
+                let mut events = Events::with_capacity(4);
+                poll.registry().register(&mut c, Token(0), Interest::READABLE).unwrap();
+                let mut buf = [0; 5];
+                let mut received = Vec::new();
+                loop {
+                    match c.read(&mut buf) {
+                        Ok(0) => break received,
+                        Ok(n) => received.extend_from_slice(&buf[..n]),
+                        Err(err) => {
+                            assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                            poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+                            assert!(!events.is_empty());
+                        },
+                    }
+                }
+            })
+        }).collect::<Vec<_>>();
+        drop(c);
+
+        let mut p = p;
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        let mut pos = 0;
+        while pos < SIZE {
+            match p.write(&data[pos..]) {
+                Ok(n) => pos += n,
+                Err(err) => {
+                    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                    poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+                    assert!(!events.is_empty());
+                },
+            }
+        }
+        drop(p);
+
+        for jh in jhs {
+            assert_eq!(jh.join().unwrap(), data);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Validated construction of the FIFO.
+
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+use ringbuf::{RingBuffer, Producer as RbProducer, Consumer as RbConsumer};
+
+use crate::{Producer, Consumer, Watermarks, EofMode};
+use crate::signal::{self, Readiness};
+use crate::state::State;
+
+/// Builder of a FIFO.
+///
+/// Configuration is validated in [`build`](Self::build),
+/// so invalid options never reach the ring buffer.
+#[derive(Clone, Debug)]
+pub struct FifoBuilder {
+    capacity: usize,
+    wm: Watermarks,
+    eof: EofMode,
+    detect_panic: bool,
+    name: Option<String>,
+}
+
+impl FifoBuilder {
+    /// Starts configuring a FIFO that holds up to `capacity` bytes.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            wm: Watermarks::default(),
+            eof: EofMode::default(),
+            detect_panic: true,
+            name: None,
+        }
+    }
+
+    /// Sets readiness thresholds, both of them must be within `1..=capacity`.
+    pub fn watermarks(mut self, wm: Watermarks) -> Self {
+        self.wm = wm;
+        self
+    }
+
+    /// Sets what the consumer gets once the producer is closed and the ring buffer is drained.
+    pub fn eof_mode(mut self, eof: EofMode) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    /// Sets whether the producer dropped during a panic closes the stream abnormally.
+    ///
+    /// Enabled by default. If disabled, such closure looks like a regular one to the consumer.
+    pub fn detect_panic(mut self, detect: bool) -> Self {
+        self.detect_panic = detect;
+        self
+    }
+
+    /// Sets a name shown in [`Debug`](std::fmt::Debug) output of both ends.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.capacity == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Capacity must be non-zero",
+            ))
+        }
+        // The ring buffer allocates one extra element.
+        if self.capacity >= isize::MAX as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Capacity is too large",
+            ))
+        }
+        let range = 1..=self.capacity;
+        if !range.contains(&self.wm.low) || !range.contains(&self.wm.high) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Watermark is out of range",
+            ))
+        }
+        Ok(())
+    }
+
+    /// Creates the FIFO.
+    pub fn build(self) -> Result<(Producer, Consumer), Error> {
+        self.validate()?;
+        let Self { capacity, wm, eof, detect_panic, name } = self;
+
+        let st = Arc::new(State::with_name(name));
+
+        let rb = RingBuffer::<u8>::new(capacity);
+
+        let (regc, src) = signal::pipe()?;
+        let (srp, regp) = signal::pipe()?;
+
+        let (mut rbp, mut rbc) = rb.split();
+        zero_storage(&mut rbp, &mut rbc);
+
+        let writable = rbp.remaining() >= wm.high;
+        let rdr = Arc::new(Readiness::new(false));
+        let rdw = Arc::new(Readiness::new(writable));
+        if !writable {
+            regp.fill()?;
+        }
+
+        let prod = Producer { reg: regp, src: Some(src), rbp, st: st.clone(), rdr: rdr.clone(), rdw: rdw.clone(), wm, detect_panic };
+        let cons = Consumer { reg: regc, srp: Some(srp), rbc, st, rdr, rdw, wm, eof };
+
+        Ok((prod, cons))
+    }
+}
+
+/// Writes zeros into every slot of the ring buffer storage.
+///
+/// The storage is allocated uninitialized, while [`Producer::write_slices`] exposes free space as plain bytes.
+fn zero_storage(rbp: &mut RbProducer<u8>, rbc: &mut RbConsumer<u8>) {
+    // The storage has one slot more than the capacity, so the ring is walked around once.
+    // `pop` leaves garbage in the slot, so bytes are moved by slices.
+    let mut zeros = [0; 256];
+    let mut left = rbp.capacity() + 1;
+    while left > 0 {
+        let n = rbp.push_slice(&zeros[..left.min(zeros.len())]).unwrap_or(0);
+        let _ = rbc.pop_slice(&mut zeros[..n]);
+        left -= n;
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn invalid() {
+        let err = FifoBuilder::new(0).build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "Capacity must be non-zero");
+
+        let err = FifoBuilder::new(usize::MAX).build().unwrap_err();
+        assert_eq!(err.to_string(), "Capacity is too large");
+
+        let wm = Watermarks { low: 1, high: 5 };
+        let err = FifoBuilder::new(4).watermarks(wm).build().unwrap_err();
+        assert_eq!(err.to_string(), "Watermark is out of range");
+    }
+
+    #[test]
+    fn options() {
+        let (p, c) = FifoBuilder::new(16)
+            .watermarks(Watermarks { low: 2, high: 8 })
+            .eof_mode(EofMode::BrokenPipe)
+            .detect_panic(false)
+            .name("fifo")
+            .build().unwrap();
+
+        assert_eq!(p.wm, Watermarks { low: 2, high: 8 });
+        assert!(!p.detect_panic);
+        assert_eq!(c.eof_mode(), EofMode::BrokenPipe);
+        assert_eq!(p.name(), Some("fifo"));
+        assert_eq!(c.name(), Some("fifo"));
+        assert_eq!(format!("{:?}", c), "Consumer { name: Some(\"fifo\"), state: Open }");
+    }
+
+    #[test]
+    fn zeroed() {
+        let (mut p, _c) = FifoBuilder::new(16).build().unwrap();
+
+        let (left, right) = p.write_slices();
+        assert_eq!(left.len() + right.len(), 16);
+        assert!(left.iter().chain(right.iter()).all(|&b| b == 0));
+    }
+}
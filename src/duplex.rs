@@ -0,0 +1,209 @@
+//! Bidirectional stream made of two FIFOs, an in-process equivalent of a socket pair.
+
+use std::io::{Write, Read, IoSlice, IoSliceMut, Error};
+
+use mio::{Registry, Token, Interest, event::Source};
+
+use crate::{create, Producer, Consumer};
+
+/// One end of a duplex stream.
+///
+/// Writes go to the other end, reads return what the other end has written.
+/// Both directions are registered in [`Poll`](mio::Poll) under a single token,
+/// readable and writable events for it come separately.
+#[derive(Debug)]
+pub struct Duplex {
+    prod: Producer,
+    cons: Consumer,
+    // Registration of each half is tracked separately, one of them may fail.
+    readable: bool,
+    writable: bool,
+}
+
+/// Creates a pair of connected duplex ends, each direction holds up to `capacity` bytes.
+pub fn create_duplex(capacity: usize) -> Result<(Duplex, Duplex), Error> {
+    let (pa, cb) = create(capacity)?;
+    let (pb, ca) = create(capacity)?;
+    Ok((
+        Duplex { prod: pa, cons: ca, readable: false, writable: false },
+        Duplex { prod: pb, cons: cb, readable: false, writable: false },
+    ))
+}
+
+impl Duplex {
+    /// Returns the producer writing to the other end.
+    pub fn producer(&self) -> &Producer {
+        &self.prod
+    }
+
+    /// Returns the consumer reading from the other end.
+    pub fn consumer(&self) -> &Consumer {
+        &self.cons
+    }
+
+    pub fn producer_mut(&mut self) -> &mut Producer {
+        &mut self.prod
+    }
+
+    pub fn consumer_mut(&mut self) -> &mut Consumer {
+        &mut self.cons
+    }
+
+    /// Splits the end into its halves.
+    ///
+    /// The halves must be deregistered beforehand if the end was registered.
+    pub fn split(self) -> (Producer, Consumer) {
+        (self.prod, self.cons)
+    }
+
+    /// Brings registration of both halves in line with `interests`, `None` means deregistered.
+    ///
+    /// If the second half fails, the first one stays updated and is tracked as such.
+    fn update(&mut self, registry: &Registry, token: Token, interests: Option<Interest>) -> Result<(), Error> {
+        let readable = interests.is_some_and(|i| i.is_readable());
+        let writable = interests.is_some_and(|i| i.is_writable());
+        update_half(&mut self.cons, registry, token, self.readable, readable, Interest::READABLE)?;
+        self.readable = readable;
+        update_half(&mut self.prod, registry, token, self.writable, writable, Interest::WRITABLE)?;
+        self.writable = writable;
+        Ok(())
+    }
+}
+
+/// Brings registration of a single half from `old` to `new` interest.
+fn update_half<S: Source>(
+    src: &mut S, registry: &Registry, token: Token,
+    old: bool, new: bool, interest: Interest,
+) -> Result<(), Error> {
+    match (old, new) {
+        (false, true) => src.register(registry, token, interest),
+        (true, true) => src.reregister(registry, token, interest),
+        (true, false) => src.deregister(registry),
+        (false, false) => Ok(()),
+    }
+}
+
+impl Source for Duplex {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        self.update(registry, token, Some(interests))
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        self.update(registry, token, Some(interests))
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> Result<(), Error> {
+        // Token doesn't matter when nothing is going to be registered.
+        self.update(registry, Token(0), None)
+    }
+}
+
+impl Write for Duplex {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.prod.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> Result<usize, Error> {
+        self.prod.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.prod.flush()
+    }
+}
+
+impl Read for Duplex {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.cons.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> Result<usize, Error> {
+        self.cons.read_vectored(bufs)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    use mio::{Poll, Events};
+
+    #[test]
+    fn write_read() {
+        let (mut a, mut b) = create_duplex(4).unwrap();
+        let mut buf = [0; 4];
+
+        assert_eq!(a.write(b"abcde").unwrap(), 4);
+        assert_eq!(b.write(b"xy").unwrap(), 2);
+
+        assert_eq!(b.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"abcd");
+        assert_eq!(a.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[0..2], b"xy");
+        assert_eq!(a.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        drop(b);
+        assert_eq!(a.read(&mut buf).unwrap(), 0);
+        assert_eq!(a.write(b"f").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn poll() {
+        let (mut a, mut b) = create_duplex(4).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let timeout = Some(Duration::from_millis(100));
+
+        poll.registry().register(&mut a, Token(0), Interest::READABLE | Interest::WRITABLE).unwrap();
+        poll.registry().register(&mut b, Token(1), Interest::READABLE).unwrap();
+
+        poll.poll(&mut events, timeout).unwrap();
+        let events_a = events.iter().filter(|e| e.token() == Token(0)).collect::<Vec<_>>();
+        assert_eq!(events_a.len(), 1);
+        assert!(events_a[0].is_writable());
+        assert!(events.iter().all(|e| e.token() == Token(0)));
+
+        assert_eq!(a.write(b"abcd").unwrap(), 4);
+        poll.poll(&mut events, timeout).unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(event.token(), Token(1));
+        assert!(event.is_readable());
+
+        poll.registry().reregister(&mut b, Token(1), Interest::WRITABLE).unwrap();
+        poll.poll(&mut events, timeout).unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(event.token(), Token(1));
+        assert!(event.is_writable());
+
+        assert_eq!(b.read(&mut [0; 4]).unwrap(), 4);
+        poll.poll(&mut events, timeout).unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(event.token(), Token(0));
+        assert!(event.is_writable());
+
+        poll.registry().deregister(&mut a).unwrap();
+        poll.registry().deregister(&mut b).unwrap();
+        assert_eq!(b.write(b"x").unwrap(), 1);
+        poll.poll(&mut events, Some(Duration::from_millis(10))).unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn register_failed() {
+        let (mut a, _b) = create_duplex(4).unwrap();
+        let poll = Poll::new().unwrap();
+
+        poll.registry().register(a.producer_mut(), Token(1), Interest::WRITABLE).unwrap();
+        assert!(poll.registry().register(&mut a, Token(0), Interest::READABLE | Interest::WRITABLE).is_err());
+        poll.registry().deregister(a.producer_mut()).unwrap();
+
+        // The consumer registered before the failure is reregistered rather than registered twice.
+        poll.registry().register(&mut a, Token(0), Interest::READABLE | Interest::WRITABLE).unwrap();
+        poll.registry().deregister(&mut a).unwrap();
+        poll.registry().register(&mut a, Token(0), Interest::READABLE).unwrap();
+    }
+}
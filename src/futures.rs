@@ -0,0 +1,130 @@
+//! [`futures-io`](futures_io) traits, enabled by `futures-io` feature.
+//!
+//! [`Producer`] implements [`AsyncWrite`] and [`Consumer`] implements [`AsyncRead`],
+//! [`poll_close`](AsyncWrite::poll_close) closes the producer, so the consumer gets end of stream after the remaining data.
+
+use std::io::{Write, Read, IoSlice, IoSliceMut, Error};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::{Producer, Consumer};
+use crate::signal::poll_io;
+
+impl AsyncWrite for Producer {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<Result<usize, Error>> {
+        poll_io(self.get_mut(), cx, |p| &p.rdw, |p| p.write(buf))
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context, bufs: &[IoSlice]) -> Poll<Result<usize, Error>> {
+        poll_io(self.get_mut(), cx, |p| &p.rdw, |p| p.write_vectored(bufs))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Error>> {
+        Poll::Ready(self.get_mut().flush())
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Error>> {
+        Poll::Ready(self.get_mut().close())
+    }
+}
+
+impl AsyncRead for Consumer {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize, Error>> {
+        // Nothing can be read into an empty buffer, waiting for data would never end.
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        poll_io(self.get_mut(), cx, |c| &c.rdr, |c| c.read(buf))
+    }
+
+    fn poll_read_vectored(self: Pin<&mut Self>, cx: &mut Context, bufs: &mut [IoSliceMut]) -> Poll<Result<usize, Error>> {
+        if bufs.iter().all(|b| b.is_empty()) {
+            return Poll::Ready(Ok(0));
+        }
+        poll_io(self.get_mut(), cx, |c| &c.rdr, |c| c.read_vectored(bufs))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::IoSliceMut;
+    use std::thread;
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::{create, create_with, Watermarks};
+
+    #[test]
+    fn copy() {
+        let (mut p, mut c) = create(7).unwrap();
+        let data = (0..1000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let sent = data.clone();
+
+        let jh = thread::spawn(move || block_on(async {
+            p.write_all(&sent).await.unwrap();
+            AsyncWriteExt::close(&mut p).await.unwrap();
+        }));
+
+        let mut received = Vec::new();
+        block_on(c.read_to_end(&mut received)).unwrap();
+        assert_eq!(received, data);
+
+        jh.join().unwrap();
+    }
+
+    #[test]
+    fn close_cons() {
+        let (mut p, c) = create(4).unwrap();
+
+        block_on(p.write_all(b"abcd")).unwrap();
+
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            drop(c);
+        });
+
+        let err = block_on(p.write_all(b"e")).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+
+        jh.join().unwrap();
+    }
+
+    #[test]
+    fn empty_bufs() {
+        let (mut p, mut c) = create(4).unwrap();
+
+        assert_eq!(block_on(c.read(&mut [])).unwrap(), 0);
+        assert_eq!(block_on(c.read_vectored(&mut [IoSliceMut::new(&mut [])])).unwrap(), 0);
+        block_on(p.write_all(b"abcd")).unwrap();
+        assert_eq!(block_on(p.write(b"")).unwrap(), 0);
+    }
+
+    #[test]
+    fn watermarks() {
+        let (mut p, mut c) = create_with(16, Watermarks { low: 4, high: 16 }).unwrap();
+
+        // The read is woken before `low` bytes are buffered.
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(std::io::Write::write(&mut p, b"ab").unwrap(), 2);
+            p
+        });
+        assert_eq!(block_on(c.read(&mut [0; 4])).unwrap(), 2);
+        let mut p = jh.join().unwrap();
+
+        // The write is woken before `high` bytes are freed.
+        block_on(p.write_all(&[0; 16])).unwrap();
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(std::io::Read::read(&mut c, &mut [0; 2]).unwrap(), 2);
+            c
+        });
+        assert_eq!(block_on(p.write(b"cdef")).unwrap(), 2);
+        jh.join().unwrap();
+    }
+}
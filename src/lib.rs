@@ -1,5 +1,14 @@
 //! Concurrent non-blocking byte FIFO buffer intended for use in [`Mio`] poll
 //!
+//! Both [`Producer`] and [`Consumer`] implement [`mio::event::Source`].
+//! Closure of the peer is reported as [`is_read_closed`](mio::event::Event::is_read_closed)
+//! to the consumer and as [`is_write_closed`](mio::event::Event::is_write_closed) to the producer,
+//! such an event may be readable or writable as well.
+//! Readiness is delivered through Unix socket pairs, so only Unix platforms are supported.
+//!
+//! With `futures-io` feature the ends also implement `AsyncWrite` and `AsyncRead` from [`futures-io`](https://docs.rs/futures-io),
+//! with `tokio` feature they implement the ones from [`tokio`](https://docs.rs/tokio).
+//!
 //! # Simple example
 //!
 //! ```rust
@@ -8,7 +17,7 @@
 //! use std::io::{Read, Write};
 //! 
 //! # fn main() {
-//! let (mut producer, mut consumer) = mio_byte_fifo::create(16);
+//! let (mut producer, mut consumer) = mio_byte_fifo::create(16).unwrap();
 //! 
 //! let data = [0, 1, 254, 255];
 //! let n = producer.write(&data).unwrap();
@@ -31,7 +40,7 @@
 //! use std::io::{Read, Write, ErrorKind};
 //! use std::thread;
 //! 
-//! use mio::{Poll, Events, Token, Ready, PollOpt};
+//! use mio::{Poll, Events, Token, Interest};
 //! 
 //! use mio_byte_fifo::{Producer, Consumer};
 //! 
@@ -41,13 +50,13 @@
 //! const READ_BUF_SIZE: usize = 7;
 //! const EVENTS_CAPACITY: usize = 4;
 //! 
-//! let (mut producer, mut consumer) = mio_byte_fifo::create(FIFO_SIZE);
+//! let (mut producer, mut consumer) = mio_byte_fifo::create(FIFO_SIZE).unwrap();
 //! let message = "The quick brown fox jumps over the lazy dog";
 //! 
 //! println!("sending message: '{}'", message);
 //! 
 //! let producer_thread = thread::spawn(move || {
-//!     let poll = Poll::new().unwrap();
+//!     let mut poll = Poll::new().unwrap();
 //!     let mut events = Events::with_capacity(EVENTS_CAPACITY);
 //!     let data = message.as_bytes();
 //!     let mut pos = 0;
@@ -73,7 +82,7 @@
 //!         }
 //!     };
 //! 
-//!     poll.register(&producer, Token(0), Ready::writable(), PollOpt::edge()).unwrap();
+//!     poll.registry().register(&mut producer, Token(0), Interest::WRITABLE).unwrap();
 //!     
 //!     if !write_data_part(&mut producer, &mut pos) {
 //!         return;
@@ -84,7 +93,7 @@
 //! 
 //!         for event in events.iter() {
 //!             assert_eq!(event.token(), Token(0));
-//!             assert!(event.readiness().is_writable());
+//!             assert!(event.is_writable());
 //! 
 //!             if !write_data_part(&mut producer, &mut pos) {
 //!                 break 'outer;
@@ -94,7 +103,7 @@
 //! });
 //! 
 //! let consumer_thread = thread::spawn(move || {
-//!     let poll = Poll::new().unwrap();
+//!     let mut poll = Poll::new().unwrap();
 //!     let mut events = Events::with_capacity(EVENTS_CAPACITY);
 //!     let mut data = String::new();
 //!     let mut buf = [0; READ_BUF_SIZE];
@@ -102,6 +111,7 @@
 //!     let mut read_data_part = |consumer: &mut Consumer, data: &mut String| {
 //!         loop {
 //!             match consumer.read(&mut buf) {
+//!                 Ok(0) => break false,
 //!                 Ok(n) => {
 //!                     let str_part = std::str::from_utf8(&buf[0..n]).unwrap();
 //!                     println!("received {} bytes: '{}'", n, str_part);
@@ -109,7 +119,6 @@
 //!                 },
 //!                 Err(err) => {
 //!                     match err.kind() {
-//!                         ErrorKind::BrokenPipe => break false,
 //!                         ErrorKind::WouldBlock => break true,
 //!                         _ => panic!("{:?}", err),
 //!                     }
@@ -118,14 +127,14 @@
 //!         }
 //!     };
 //! 
-//!     poll.register(&consumer, Token(0), Ready::readable(), PollOpt::edge()).unwrap();
+//!     poll.registry().register(&mut consumer, Token(0), Interest::READABLE).unwrap();
 //!     
 //!     'outer: loop {
 //!         poll.poll(&mut events, None).unwrap();
 //! 
 //!         for event in events.iter() {
 //!             assert_eq!(event.token(), Token(0));
-//!             assert!(event.readiness().is_readable());
+//!             assert!(event.is_readable() || event.is_read_closed());
 //!             if !read_data_part(&mut consumer, &mut data) {
 //!                 break 'outer;
 //!             }
@@ -145,408 +154,742 @@
 //! [`Mio`]: https://docs.rs/mio/
 //!
 
+#[cfg(not(unix))]
+compile_error!("mio-byte-fifo requires a Unix platform");
+
 extern crate mio;
 extern crate ringbuf;
-
-
-use std::io::{Write, Read, Error, ErrorKind};
-use std::sync::{Arc, atomic::{fence, AtomicBool, Ordering}};
-
-use mio::{Evented, Poll, Token, Ready, PollOpt, Registration, SetReadiness};
+extern crate libc;
+#[cfg(feature = "futures-io")]
+extern crate futures_io;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+
+mod blocking;
+mod broadcast;
+mod builder;
+mod duplex;
+mod mpsc;
+mod packet;
+#[cfg(feature = "futures-io")]
+mod futures;
+#[cfg(feature = "tokio")]
+mod tokio_io;
+mod signal;
+mod state;
+
+
+use std::io::{Write, Read, BufRead, IoSlice, IoSliceMut, Error, ErrorKind};
+use std::os::unix::io::AsRawFd;
+use std::{fmt, iter, mem, slice, thread};
+use std::sync::{Arc, atomic::{fence, Ordering}};
+
+use mio::{Registry, Token, Interest, event::Source, unix::SourceFd};
 
 use ringbuf::{
-    RingBuffer,
     Producer as RbProducer, Consumer as RbConsumer,
-    PushSliceError, PopSliceError,
-    WriteIntoError, ReadFromError,
+    PopSliceError,
+    PushAccessError, PopAccessError,
+    WriteIntoError,
 };
 
+use signal::{PipeReader, PipeWriter, Readiness};
+use state::State;
+
+pub use blocking::{BlockingProducer, BlockingConsumer};
+pub use broadcast::{create_broadcast, BroadcastProducer, BroadcastConsumer, LagPolicy};
+pub use builder::FifoBuilder;
+pub use duplex::{create_duplex, Duplex};
+pub use mpsc::{create_mpsc, SharedProducer};
+pub use packet::{create_packet, PacketProducer, PacketConsumer};
+pub use state::FifoState;
+
 #[derive(Debug)]
 pub enum TransmitError {
     This(Error),
     Other(Error),
 }
 
+/// Readiness thresholds of the FIFO.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watermarks {
+    /// Number of buffered bytes that makes the consumer readable.
+    pub low: usize,
+    /// Amount of free space that makes the producer writable.
+    pub high: usize,
+}
+
+impl Default for Watermarks {
+    /// Readiness is signaled as soon as a single byte is buffered or freed.
+    fn default() -> Self {
+        Self { low: 1, high: 1 }
+    }
+}
+
+/// What the consumer gets once the producer is closed and the ring buffer is drained.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EofMode {
+    /// Reading returns `Ok(0)` as [`Read`] convention prescribes.
+    #[default]
+    Eof,
+    /// Reading fails with [`ErrorKind::BrokenPipe`].
+    BrokenPipe,
+}
+
 pub struct Producer {
-    reg: Registration,
-    src: SetReadiness,
+    reg: PipeWriter,
+    src: Option<PipeWriter>,
     rbp: RbProducer<u8>,
-    cls: Arc<AtomicBool>,
+    st: Arc<State>,
+    rdr: Arc<Readiness>,
+    rdw: Arc<Readiness>,
+    wm: Watermarks,
+    detect_panic: bool,
 }
 
 pub struct Consumer {
-    reg: Registration,
-    srp: SetReadiness,
+    reg: PipeReader,
+    srp: Option<PipeReader>,
     rbc: RbConsumer<u8>,
-    cls: Arc<AtomicBool>,
+    st: Arc<State>,
+    rdr: Arc<Readiness>,
+    rdw: Arc<Readiness>,
+    wm: Watermarks,
+    eof: EofMode,
+}
+
+/// Creates a FIFO with default options.
+///
+/// Fails if `capacity` is zero, see [`FifoBuilder`] for other options.
+pub fn create(capacity: usize) -> Result<(Producer, Consumer), Error> {
+    FifoBuilder::new(capacity).build()
 }
 
-pub fn create(capacity: usize) -> (Producer, Consumer) {
-    let flag = Arc::new(AtomicBool::new(true));
+/// Creates a FIFO that signals readiness according to `wm`.
+///
+/// Both watermarks must be within `1..=capacity`.
+pub fn create_with(capacity: usize, wm: Watermarks) -> Result<(Producer, Consumer), Error> {
+    FifoBuilder::new(capacity).watermarks(wm).build()
+}
 
-    let rb = RingBuffer::<u8>::new(capacity);
+/// Copies bytes from `src` slices into `dst` slices in order.
+///
+/// Returns the number of bytes copied.
+fn copy_slices<'a, 'b, I, O>(src: I, dst: O) -> usize
+where I: IntoIterator<Item = &'a [u8]>, O: IntoIterator<Item = &'b mut [u8]> {
+    let mut dst = dst.into_iter();
+    let mut out: &mut [u8] = &mut [];
+    let mut num = 0;
+    for mut inp in src {
+        while !inp.is_empty() {
+            if out.is_empty() {
+                match dst.next() {
+                    Some(next) => out = next,
+                    None => return num,
+                }
+                continue;
+            }
+            let n = inp.len().min(out.len());
+            let (head, tail) = mem::take(&mut out).split_at_mut(n);
+            head.copy_from_slice(&inp[..n]);
+            out = tail;
+            inp = &inp[n..];
+            num += n;
+        }
+    }
+    num
+}
 
-    let (regp, srp) = Registration::new2();
-    let (regc, src) = Registration::new2();
+impl Producer {
+    /// Returns free space of the ring buffer as two slices to write into directly.
+    ///
+    /// The second slice may be empty. Written bytes become available to the consumer
+    /// only after [`commit`](Self::commit).
+    pub fn write_slices(&mut self) -> (&mut [u8], &mut [u8]) {
+        let mut raw = None;
+        let _ = unsafe { self.rbp.push_access(|left, right| {
+            raw = Some((left.as_mut_ptr(), left.len(), right.as_mut_ptr(), right.len()));
+            Ok::<_, ()>((0, ()))
+        }) };
+        match raw {
+            Some((lp, ll, rp, rl)) => unsafe {(
+                slice::from_raw_parts_mut(lp, ll),
+                slice::from_raw_parts_mut(rp, rl),
+            )},
+            None => (&mut [], &mut []),
+        }
+    }
 
-    let (rbp, rbc) = rb.split();
+    /// Makes `count` bytes written into [`write_slices`](Self::write_slices) available to the consumer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is greater than the free space of the ring buffer.
+    pub fn commit(&mut self, count: usize) -> Result<(), Error> {
+        if let Some(e) = self.closed_error() {
+            return Err(e);
+        }
 
-    let prod = Producer { reg: regp, src, rbp, cls: flag.clone() };
-    let cons = Consumer { reg: regc, srp, rbc, cls: flag };
+        let res = self.push_with(|left, right| match count <= left.len() + right.len() {
+            true => Ok(count),
+            false => Err(()),
+        });
+        match res {
+            Ok(Ok(_)) => self.update(),
+            Err(PushAccessError::Full) if count == 0 => Ok(()),
+            _ => panic!("Cannot commit {} bytes, only {} are free", count, self.rbp.remaining()),
+        }
+    }
 
-    (prod, cons)
-}
+    /// Closes the producer without dropping it.
+    ///
+    /// The consumer gets [`is_read_closed`](mio::event::Event::is_read_closed) event,
+    /// it can read the remaining data and then gets end of stream.
+    /// The producer itself stays registered until it is deregistered or dropped.
+    pub fn close(&mut self) -> Result<(), Error> {
+        self.st.close_producer();
+        fence(Ordering::SeqCst);
+        // Closing the write end hangs up the pipe registered by the consumer.
+        drop(self.src.take());
+        self.rdr.progress();
+        Ok(())
+    }
 
-impl Evented for Producer {
-    fn register(&self, poll: &Poll, token: Token, interest: Ready, poll_opt: PollOpt) -> Result<(), Error> {
-        poll.register(&self.reg, token, interest, poll_opt)
+    /// Closes the producer like [`close`](Self::close) does, but with a reason.
+    ///
+    /// The consumer reads the remaining data and then gets `err` instead of end of stream.
+    /// Does nothing if the producer is already closed.
+    pub fn close_with_error(&mut self, err: Error) -> Result<(), Error> {
+        if !self.state().is_producer_closed() {
+            self.st.set_reason(err);
+        }
+        self.close()
     }
 
-    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, poll_opt: PollOpt) -> Result<(), Error> {
-        poll.reregister(&self.reg, token, interest, poll_opt)
+    /// Returns which ends of the FIFO are closed.
+    pub fn state(&self) -> FifoState {
+        self.st.get()
     }
 
-    fn deregister(&self, poll: &Poll) -> Result<(), Error> {
-        poll.deregister(&self.reg)
+    /// Returns the name given to the FIFO by [`FifoBuilder::name`].
+    pub fn name(&self) -> Option<&str> {
+        self.st.name()
     }
-}
 
-impl Evented for Consumer {
-    fn register(&self, poll: &Poll, token: Token, interest: Ready, poll_opt: PollOpt) -> Result<(), Error> {
-        poll.register(&self.reg, token, interest, poll_opt)
+    /// Wraps the producer into [`BlockingProducer`] that waits until writing can make progress.
+    pub fn into_blocking(self) -> BlockingProducer {
+        BlockingProducer::new(self)
     }
 
-    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, poll_opt: PollOpt) -> Result<(), Error> {
-        poll.reregister(&self.reg, token, interest, poll_opt)
+    /// Checks whether the consumer is closed.
+    pub fn is_peer_closed(&self) -> bool {
+        self.state().is_consumer_closed()
     }
 
-    fn deregister(&self, poll: &Poll) -> Result<(), Error> {
-        poll.deregister(&self.reg)
+    /// Returns the number of bytes written into the FIFO since it was created.
+    pub fn total_written(&self) -> u64 {
+        self.st.written()
     }
-}
 
-impl Drop for Producer {
-    fn drop(&mut self) {
-        self.cls.store(false, Ordering::SeqCst);
-        self.src.set_readiness(Ready::all()).unwrap();
-        fence(Ordering::SeqCst);
+    /// Returns the number of bytes read from the FIFO since it was created.
+    ///
+    /// Skipped bytes are counted too, bytes left unread when the consumer is closed are not.
+    /// Never exceeds [`total_written`](Self::total_written) taken afterwards.
+    pub fn total_read(&self) -> u64 {
+        self.st.read()
     }
-}
 
-impl Drop for Consumer {
-    fn drop(&mut self) {
-        self.cls.store(false, Ordering::SeqCst);
-        self.srp.set_readiness(Ready::all()).unwrap();
-        fence(Ordering::SeqCst);
+    /// Returns the number of written bytes the consumer hasn't read before it was closed.
+    ///
+    /// These bytes are the tail of the written data. Returns zero while the consumer is open.
+    pub fn lost_bytes(&self) -> u64 {
+        if !self.is_peer_closed() {
+            return 0;
+        }
+        self.total_written() - self.total_read()
     }
-}
 
-impl Write for Producer {
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
-        if !self.cls.load(Ordering::SeqCst) {
-            return Err(Error::new(
+    /// Error to return when the FIFO is closed.
+    fn closed_error(&self) -> Option<Error> {
+        let state = self.state();
+        if state.is_open() {
+            None
+        } else if state.is_producer_closed() {
+            Some(Error::new(
+                ErrorKind::BrokenPipe,
+                "Producer was closed",
+            ))
+        } else {
+            Some(Error::new(
                 ErrorKind::BrokenPipe,
                 "Consumer was closed",
             ))
         }
+    }
 
-        let empty = self.rbp.is_empty();
-        match self.rbp.push_slice(buf) {
-            Ok(num) => {
-                if num > 0 && empty {
-                    let res = self.src.set_readiness(Ready::readable());
-                    fence(Ordering::SeqCst);
-                    res
-                } else {
-                    Ok(())
-                }.and(Ok(num))
-            },
-            Err(err) => match err {
-                PushSliceError::Full => Err(Error::new(
-                    ErrorKind::WouldBlock,
-                    "Ring buffer is full",
-                )),
-            }
-        }
+    /// Writes into free space of the ring buffer with `f`, which returns the number of bytes written.
+    ///
+    /// The bytes are accounted before the consumer can see them, so the read counter never gets ahead.
+    fn push_with<E, F>(&mut self, f: F) -> Result<Result<usize, E>, PushAccessError>
+    where F: FnOnce(&mut [u8], &mut [u8]) -> Result<usize, E> {
+        let st = &self.st;
+        let res = unsafe { self.rbp.push_access(|left, right| {
+            let num = f(left, right)?;
+            st.add_written(num);
+            Ok((num, ()))
+        }) };
+        res.map(|res| res.map(|(num, ())| num))
     }
 
-    fn flush(&mut self) -> Result<(), Error> {
+    /// Makes consumer readable when the ring buffer has enough data
+    /// and clears own writable readiness when it lacks free space.
+    fn update(&self) -> Result<(), Error> {
+        let (rbp, st, wm) = (&self.rbp, &self.st, &self.wm);
+        self.rdr.progress();
+        let src = &self.src;
+        self.rdr.raise(|| rbp.len() >= wm.low, || src.as_ref().map_or(Ok(()), PipeWriter::notify))?;
+        if rbp.remaining() < wm.high {
+            self.rdw.clear(|| rbp.remaining() < wm.high && st.is_open(), || self.reg.fill())?;
+        }
         Ok(())
     }
 }
 
-impl Read for Consumer {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        let full = self.rbc.is_full();
-        match self.rbc.pop_slice(buf) {
-            Ok(num) => {
-                if num > 0 && full {
-                    let res = self.srp.set_readiness(Ready::writable());
-                    fence(Ordering::SeqCst);
-                    res
-                } else {
-                    Ok(())
-                }.and(Ok(num))
-            },
-            Err(err) => match err {
-                PopSliceError::Empty => Err({
-                    if !self.cls.load(Ordering::SeqCst) {
-                        Error::new(
-                            ErrorKind::BrokenPipe,
-                            "Producer was closed",
-                        )
-                    } else {
-                        Error::new(
-                            ErrorKind::WouldBlock,
-                            "Ring buffer is empty",
-                        )
-                    }
-                }),
-            }
+impl Consumer {
+    /// Returns data stored in the ring buffer as two slices, older bytes go first.
+    ///
+    /// The second slice may be empty. Bytes are removed from the ring buffer
+    /// only after [`consume`](Self::consume).
+    pub fn read_slices(&self) -> (&[u8], &[u8]) {
+        let mut res: (&[u8], &[u8]) = (&[], &[]);
+        if self.state().is_consumer_closed() {
+            return res;
         }
+        let _ = self.rbc.access(|left, right| {
+            res = unsafe {(
+                slice::from_raw_parts(left.as_ptr(), left.len()),
+                slice::from_raw_parts(right.as_ptr(), right.len()),
+            )};
+        });
+        res
     }
-}
 
-pub trait WriteTransmit {
-    fn write_transmit(&mut self, other: &mut dyn Read, count: Option<usize>)
-    -> Result<usize, TransmitError>;
-}
+    /// Removes `count` bytes returned by [`read_slices`](Self::read_slices) from the ring buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is greater than the number of bytes stored in the ring buffer.
+    pub fn consume(&mut self, count: usize) -> Result<(), Error> {
+        let len = self.available();
+        if count > len {
+            panic!("Cannot consume {} bytes, only {} are stored", count, len);
+        }
+        match unsafe { self.rbc.pop_access(|_, _| Ok::<_, ()>((count, ()))) } {
+            Ok(_) => self.update(count),
+            Err(PopAccessError::Empty) => Ok(()),
+            Err(PopAccessError::BadLen) => unreachable!(),
+        }
+    }
 
-pub trait ReadTransmit {
-    fn read_transmit(&mut self, other: &mut dyn Write, count: Option<usize>)
-    -> Result<usize, TransmitError>;
-}
+    /// Returns the number of bytes that can be read, the closed consumer reads nothing.
+    fn available(&self) -> usize {
+        match self.state().is_consumer_closed() {
+            true => 0,
+            false => self.rbc.len(),
+        }
+    }
 
-impl WriteTransmit for Producer {
-    fn write_transmit(&mut self, other: &mut dyn Read, count: Option<usize>)
-    -> Result<usize, TransmitError> {
-        if !self.cls.load(Ordering::SeqCst) {
-            return Err(TransmitError::This(Error::new(
-                ErrorKind::BrokenPipe, "Consumer was closed",
-            )))
-        }
-
-        let empty = self.rbp.is_empty();
-        match self.rbp.read_from(other, count) {
-            Ok(num) => {
-                if num > 0 && empty {
-                    let res = self.src.set_readiness(Ready::readable());
-                    fence(Ordering::SeqCst);
-                    res
-                } else {
-                    Ok(())
-                }.and(Ok(num)).or_else(|e| {
-                    Err(TransmitError::This(e))
-                })
-            },
-            Err(err) => Err(match err {
-                ReadFromError::Read(e) => TransmitError::Other(e),
-                ReadFromError::RbFull => TransmitError::This(Error::new(
-                    ErrorKind::WouldBlock, "Ring buffer is full",
-                )),
-            }),
+    /// Removes up to `count` bytes from the ring buffer without copying them anywhere.
+    ///
+    /// Returns the number of bytes removed, fails the same way as [`read`](Read::read) does.
+    pub fn skip(&mut self, count: usize) -> Result<usize, Error> {
+        let len = self.available();
+        if len == 0 {
+            return match self.empty_result(0) {
+                Some(res) => res.and(Ok(0)),
+                None => self.skip(count),
+            }
         }
+        let num = count.min(len);
+        self.consume(num).and(Ok(num))
     }
-}
 
-impl ReadTransmit for Consumer {
-    fn read_transmit(&mut self, other: &mut dyn Write, count: Option<usize>)
-    -> Result<usize, TransmitError> {
-        let full = self.rbc.is_full();
-        match self.rbc.write_into(other, count) {
-            Ok(num) => {
-                if num > 0 && full {
-                    let res = self.srp.set_readiness(Ready::writable());
-                    fence(Ordering::SeqCst);
-                    res
-                } else {
-                    Ok(())
-                }.and(Ok(num)).or_else(|e| {
-                    Err(TransmitError::This(e))
-                })
-            },
-            Err(err) => Err(match err {
-                WriteIntoError::Write(e) => TransmitError::Other(e),
-                WriteIntoError::RbEmpty => TransmitError::This({
-                    if !self.cls.load(Ordering::SeqCst) {
-                        Error::new(
-                            ErrorKind::BrokenPipe, "Producer was closed",
-                        )
-                    } else {
-                        Error::new(
-                            ErrorKind::WouldBlock, "Ring buffer is empty",
-                        )
-                    }
-                }),
-            }),
+    /// Copies buffered bytes into `buf` without removing them from the ring buffer.
+    ///
+    /// Doesn't affect readiness of the producer.
+    pub fn peek(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.peek_at(0, buf)
+    }
+
+    /// Copies buffered bytes starting from `offset` into `buf` without removing them from the ring buffer.
+    ///
+    /// Fails the same way as [`read`](Read::read) does if there are no bytes past `offset`.
+    pub fn peek_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, Error> {
+        let (left, right) = self.read_slices();
+        let (left, right) = match left.get(offset..) {
+            Some(rest) => (rest, right),
+            None => (&[][..], right.get((offset - left.len())..).unwrap_or(&[])),
+        };
+        if left.is_empty() && right.is_empty() {
+            return match self.empty_result(offset) {
+                Some(res) => res.and(Ok(0)),
+                None => self.peek_at(offset, buf),
+            }
         }
+        Ok(copy_slices(iter::once(left).chain(iter::once(right)), iter::once(buf)))
     }
-}
 
+    /// Returns the end of stream behavior of the consumer.
+    pub fn eof_mode(&self) -> EofMode {
+        self.eof
+    }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    /// Sets what reading returns once the producer is closed and the ring buffer is drained.
+    pub fn set_eof_mode(&mut self, mode: EofMode) {
+        self.eof = mode;
+    }
 
-    use std::thread;
-    use std::time::{Duration};
+    /// Result to return when the ring buffer is found to contain no more than `offset` bytes.
+    ///
+    /// `Ok` means end of stream. Returns `None` if some data arrived since then.
+    fn empty_result(&self, offset: usize) -> Option<Result<(), Error>> {
+        let state = self.state();
+        if state.is_open() {
+            return Some(Err(Error::new(
+                ErrorKind::WouldBlock,
+                "Ring buffer is empty",
+            )));
+        }
+        if self.available() > offset {
+            return None;
+        }
+        if !state.is_consumer_closed() {
+            if let Some(err) = self.st.reason() {
+                return Some(Err(err));
+            }
+        }
+        Some(match self.eof {
+            EofMode::Eof => Ok(()),
+            EofMode::BrokenPipe => Err(Error::new(
+                ErrorKind::BrokenPipe,
+                if state.is_consumer_closed() {
+                    "Consumer was closed"
+                } else {
+                    "Producer was closed"
+                },
+            )),
+        })
+    }
 
-    use mio::{Events};
+    /// Closes the consumer without dropping it.
+    ///
+    /// The producer gets [`is_write_closed`](mio::event::Event::is_write_closed) event
+    /// and fails to write from now on. Reading behaves as at the end of stream,
+    /// the remaining data is discarded.
+    /// The consumer itself stays registered until it is deregistered or dropped.
+    pub fn close(&mut self) -> Result<(), Error> {
+        self.st.close_consumer();
+        fence(Ordering::SeqCst);
+        // Closing the read end makes the pipe registered by the producer report an error.
+        drop(self.srp.take());
+        self.rdw.progress();
+        Ok(())
+    }
 
+    /// Returns which ends of the FIFO are closed.
+    pub fn state(&self) -> FifoState {
+        self.st.get()
+    }
 
-    #[test]
-    fn reg_set_r() {
-        let (reg, sr) = Registration::new2();
-        let poll = Poll::new().unwrap();
-        let mut events = Events::with_capacity(16);
+    /// Returns the name given to the FIFO by [`FifoBuilder::name`].
+    pub fn name(&self) -> Option<&str> {
+        self.st.name()
+    }
 
-        let jh = thread::spawn(move || {
-            thread::sleep(Duration::from_millis(10));
-            sr.set_readiness(Ready::readable()).unwrap();
-        });
+    /// Wraps the consumer into [`BlockingConsumer`] that waits until reading can make progress.
+    pub fn into_blocking(self) -> BlockingConsumer {
+        BlockingConsumer::new(self)
+    }
 
-        poll.register(&reg, Token(0), Ready::readable(), PollOpt::edge()).unwrap();
+    /// Checks whether the producer is closed.
+    pub fn is_peer_closed(&self) -> bool {
+        self.state().is_producer_closed()
+    }
 
-        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
-        let mut hdl = false;
-        for e in events.iter() {
-            assert_eq!(e.token().0, 0);
-            assert!(e.readiness().is_readable());
-            hdl = true;
-        }
-        assert!(hdl);
+    /// Returns the number of bytes written into the FIFO since it was created.
+    pub fn total_written(&self) -> u64 {
+        self.st.written()
+    }
 
-        poll.deregister(&reg).unwrap();
+    /// Returns the number of bytes read from the FIFO since it was created, including skipped ones.
+    ///
+    /// Never exceeds [`total_written`](Self::total_written) taken afterwards.
+    pub fn total_read(&self) -> u64 {
+        self.st.read()
+    }
 
-        jh.join().unwrap();
+    /// Checks whether the producer was dropped during a panic.
+    ///
+    /// In this case reading fails with [`ErrorKind::UnexpectedEof`] once the remaining data is read.
+    pub fn is_abnormally_closed(&self) -> bool {
+        self.st.is_abnormal()
     }
 
-    #[test]
-    fn reg_set_w() {
-        let (reg, sr) = Registration::new2();
-        let poll = Poll::new().unwrap();
-        let mut events = Events::with_capacity(16);
+    /// Accounts `count` bytes just read,
+    /// makes producer writable when the ring buffer has enough free space
+    /// and clears own readable readiness when it lacks data.
+    fn update(&self, count: usize) -> Result<(), Error> {
+        let (rbc, st, wm) = (&self.rbc, &self.st, &self.wm);
+        st.add_read(count);
+        self.rdw.progress();
+        let srp = &self.srp;
+        self.rdw.raise(|| rbc.remaining() >= wm.high, || srp.as_ref().map_or(Ok(()), PipeReader::drain))?;
+        if rbc.len() < wm.low {
+            self.rdr.clear(|| rbc.len() < wm.low && st.is_open(), || self.reg.drain())?;
+        }
+        Ok(())
+    }
+}
 
-        let jh = thread::spawn(move || {
-            thread::sleep(Duration::from_millis(10));
-            sr.set_readiness(Ready::writable()).unwrap();
-        });
+impl Source for Producer {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        SourceFd(&self.reg.as_raw_fd()).register(registry, token, interests)
+    }
 
-        poll.register(&reg, Token(0), Ready::writable(), PollOpt::edge()).unwrap();
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        SourceFd(&self.reg.as_raw_fd()).reregister(registry, token, interests)
+    }
 
-        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
-        let mut hdl = false;
-        for e in events.iter() {
-            assert_eq!(e.token().0, 0);
-            assert!(e.readiness().is_writable());
-            hdl = true;
-        }
-        assert!(hdl);
+    fn deregister(&mut self, registry: &Registry) -> Result<(), Error> {
+        SourceFd(&self.reg.as_raw_fd()).deregister(registry)
+    }
+}
 
-        poll.deregister(&reg).unwrap();
+impl Source for Consumer {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        SourceFd(&self.reg.as_raw_fd()).register(registry, token, interests)
+    }
 
-        jh.join().unwrap();
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        SourceFd(&self.reg.as_raw_fd()).reregister(registry, token, interests)
     }
 
-    #[test]
-    fn reg_set_twice() {
-        let (reg, sr) = Registration::new2();
-        let poll = Poll::new().unwrap();
-        let mut events = Events::with_capacity(16);
+    fn deregister(&mut self, registry: &Registry) -> Result<(), Error> {
+        SourceFd(&self.reg.as_raw_fd()).deregister(registry)
+    }
+}
 
-        let jh = thread::spawn(move || {
-            thread::sleep(Duration::from_millis(10));
-            sr.set_readiness(Ready::readable()).unwrap();
+impl fmt::Debug for Producer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Producer")
+            .field("name", &self.name())
+            .field("state", &self.state())
+            .finish()
+    }
+}
 
-            thread::sleep(Duration::from_millis(10));
-            sr.set_readiness(Ready::readable()).unwrap();
-        });
+impl fmt::Debug for Consumer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Consumer")
+            .field("name", &self.name())
+            .field("state", &self.state())
+            .finish()
+    }
+}
 
-        poll.register(&reg, Token(0), Ready::readable(), PollOpt::edge()).unwrap();
+impl Drop for Producer {
+    fn drop(&mut self) {
+        if self.detect_panic && thread::panicking() && !self.state().is_producer_closed() {
+            self.st.set_abnormal();
+            self.st.set_reason(Error::new(
+                ErrorKind::UnexpectedEof,
+                "Producer panicked",
+            ));
+        }
+        // There is no way to report an error from here,
+        // the consumer finds out about closure from the hang-up anyway.
+        let _ = self.close();
+    }
+}
 
-        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
-        let mut hdl = false;
-        for e in events.iter() {
-            assert_eq!(e.token().0, 0);
-            assert!(e.readiness().is_readable());
-            hdl = true;
+impl Drop for Consumer {
+    fn drop(&mut self) {
+        // See `Drop for Producer`.
+        let _ = self.close();
+    }
+}
+
+impl Write for Producer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if let Some(e) = self.closed_error() {
+            return Err(e);
+        }
+        // A full ring buffer would refuse even an empty write.
+        if buf.is_empty() {
+            return Ok(0);
         }
-        assert!(hdl);
 
-        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
-        let mut hdl = false;
-        for e in events.iter() {
-            assert_eq!(e.token().0, 0);
-            assert!(e.readiness().is_readable());
-            hdl = true;
+        let res = self.push_with(|left, right| {
+            Ok::<_, ()>(copy_slices(iter::once(buf), iter::once(left).chain(iter::once(right))))
+        });
+        match res {
+            Ok(Ok(num)) => self.update().and(Ok(num)),
+            Ok(Err(())) => unreachable!(),
+            Err(_) => Err(Error::new(
+                ErrorKind::WouldBlock,
+                "Ring buffer is full",
+            )),
         }
-        assert!(hdl);
+    }
 
-        poll.deregister(&reg).unwrap();
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> Result<usize, Error> {
+        if let Some(e) = self.closed_error() {
+            return Err(e);
+        }
 
-        jh.join().unwrap();
+        let num = {
+            let (left, right) = self.write_slices();
+            copy_slices(bufs.iter().map(|b| &**b), iter::once(left).chain(iter::once(right)))
+        };
+        if num == 0 && bufs.iter().any(|b| !b.is_empty()) {
+            return Err(Error::new(
+                ErrorKind::WouldBlock,
+                "Ring buffer is full",
+            ))
+        }
+        self.commit(num).and(Ok(num))
     }
 
-    #[test]
-    fn reg_drop() {
-        let (reg, sr) = Registration::new2();
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
 
-        let jh = thread::spawn(move || {
-            let _ = reg;
-        });
+impl Read for Consumer {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let res = match self.available() {
+            0 => Err(PopSliceError::Empty),
+            _ => self.rbc.pop_slice(buf),
+        };
+        match res {
+            Ok(num) => self.update(num).and(Ok(num)),
+            Err(err) => match err {
+                PopSliceError::Empty => match self.empty_result(0) {
+                    Some(res) => res.and(Ok(0)),
+                    None => self.read(buf),
+                },
+            }
+        }
+    }
 
-        thread::sleep(Duration::from_millis(10));
-        sr.set_readiness(Ready::readable()).unwrap();
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> Result<usize, Error> {
+        let num = {
+            let (left, right) = self.read_slices();
+            copy_slices(iter::once(left).chain(iter::once(right)), bufs.iter_mut().map(|b| &mut **b))
+        };
+        if num == 0 {
+            if self.available() == 0 {
+                return match self.empty_result(0) {
+                    Some(res) => res.and(Ok(0)),
+                    None => self.read_vectored(bufs),
+                }
+            }
+            // Data arrived after the slices were taken, `Ok(0)` would mean end of stream.
+            if bufs.iter().any(|b| !b.is_empty()) {
+                return self.read_vectored(bufs);
+            }
+        }
+        self.consume(num).and(Ok(num))
+    }
+}
 
-        jh.join().unwrap();
+impl BufRead for Consumer {
+    fn fill_buf(&mut self) -> Result<&[u8], Error> {
+        if self.available() == 0 {
+            if let Some(res) = self.empty_result(0) {
+                return res.and(Ok(&[][..]));
+            }
+        }
+        Ok(self.read_slices().0)
     }
 
-    #[test]
-    fn dereg_reg() {
-        let (reg, sr) = Registration::new2();
-        let poll = Poll::new().unwrap();
-        let mut events = Events::with_capacity(16);
+    fn consume(&mut self, amt: usize) {
+        // `BufRead::consume` cannot report errors, so readiness errors are dropped here.
+        let _ = Consumer::consume(self, amt);
+    }
+}
 
-        let jh = thread::spawn(move || {
-            thread::sleep(Duration::from_millis(10));
-            sr.set_readiness(Ready::readable()).unwrap();
+pub trait WriteTransmit {
+    fn write_transmit(&mut self, other: &mut dyn Read, count: Option<usize>)
+    -> Result<usize, TransmitError>;
+}
 
-            thread::sleep(Duration::from_millis(10));
-            sr.set_readiness(Ready::readable()).unwrap();
-        });
+pub trait ReadTransmit {
+    fn read_transmit(&mut self, other: &mut dyn Write, count: Option<usize>)
+    -> Result<usize, TransmitError>;
+}
+
+impl WriteTransmit for Producer {
+    fn write_transmit(&mut self, other: &mut dyn Read, count: Option<usize>)
+    -> Result<usize, TransmitError> {
+        if let Some(e) = self.closed_error() {
+            return Err(TransmitError::This(e));
+        }
 
-        poll.register(&reg, Token(0), Ready::readable(), PollOpt::edge()).unwrap();
+        let res = self.push_with(|left, _| {
+            let len = count.map_or(left.len(), |c| c.min(left.len()));
+            match other.read(&mut left[..len])? {
+                num if num <= len => Ok(num),
+                _ => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Read operation returned invalid number",
+                )),
+            }
+        });
+        match res {
+            Ok(Ok(num)) => self.update().and(Ok(num)).map_err(TransmitError::This),
+            Ok(Err(e)) => Err(TransmitError::Other(e)),
+            Err(_) => Err(TransmitError::This(Error::new(
+                ErrorKind::WouldBlock, "Ring buffer is full",
+            ))),
+        }
+    }
+}
 
-        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
-        let mut hdl = false;
-        for e in events.iter() {
-            assert_eq!(e.token().0, 0);
-            assert!(e.readiness().is_readable());
-            hdl = true;
+impl ReadTransmit for Consumer {
+    fn read_transmit(&mut self, other: &mut dyn Write, count: Option<usize>)
+    -> Result<usize, TransmitError> {
+        let res = match self.available() {
+            0 => Err(WriteIntoError::RbEmpty),
+            _ => self.rbc.write_into(other, count),
+        };
+        match res {
+            Ok(num) => self.update(num).and(Ok(num)).map_err(TransmitError::This),
+            Err(err) => match err {
+                WriteIntoError::Write(e) => Err(TransmitError::Other(e)),
+                WriteIntoError::RbEmpty => match self.empty_result(0) {
+                    Some(res) => res.and(Ok(0)).map_err(TransmitError::This),
+                    None => self.read_transmit(other, count),
+                },
+            },
         }
-        assert!(hdl);
+    }
+}
 
-        poll.deregister(&reg).unwrap();
 
-        poll.register(&reg, Token(1), Ready::readable(), PollOpt::edge()).unwrap();
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
-        let mut hdl = false;
-        for e in events.iter() {
-            assert_eq!(e.token().0, 1);
-            assert!(e.readiness().is_readable());
-            hdl = true;
-        }
-        assert!(hdl);
+    use std::thread;
+    use std::time::{Duration};
+
+    use mio::{Poll, Events};
 
-        poll.deregister(&reg).unwrap();
 
-        jh.join().unwrap();
-    }
-    
     #[test]
     fn write_read() {
-        let (mut p, mut c) = create(16);
+        let (mut p, mut c) = create(16).unwrap();
 
         assert_eq!(p.write(b"abcdef").unwrap(), 6);
 
@@ -557,7 +900,7 @@ mod test {
 
     #[test]
     fn write_read_concat() {
-        let (mut p, mut c) = create(16);
+        let (mut p, mut c) = create(16).unwrap();
 
         assert_eq!(p.write(b"abc").unwrap(), 3);
         assert_eq!(p.write(b"def").unwrap(), 3);
@@ -569,7 +912,7 @@ mod test {
 
     #[test]
     fn write_read_split() {
-        let (mut p, mut c) = create(16);
+        let (mut p, mut c) = create(16).unwrap();
 
         assert_eq!(p.write(b"abcdef").unwrap(), 6);
 
@@ -582,7 +925,7 @@ mod test {
 
     #[test]
     fn write_read_empty() {
-        let (mut p, mut c) = create(16);
+        let (mut p, mut c) = create(16).unwrap();
 
         let mut buf = [0; 6];
         
@@ -597,7 +940,7 @@ mod test {
 
     #[test]
     fn write_read_full() {
-        let (mut p, mut c) = create(8);
+        let (mut p, mut c) = create(8).unwrap();
 
         let range: Vec<u8> = (0..8).collect();
         let mut buf = [0; 6];
@@ -618,36 +961,28 @@ mod test {
 
     #[test]
     fn read_block() {
-        let (_p, mut c) = create(16);
+        let (_p, mut c) = create(16).unwrap();
 
         let mut buf = [0; 4];
-        match c.read(&mut buf) {
-            Ok(_) => panic!(),
-            Err(err) => {
-                assert_eq!(err.kind(), ErrorKind::WouldBlock);
-                assert_eq!(err.get_ref().unwrap().description(), "Ring buffer is empty");
-            }
-        }
+        let err = c.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert_eq!(err.to_string(), "Ring buffer is empty");
     }
 
     #[test]
     fn write_block() {
         const SIZE: usize = 16;
-        let (mut p, _c) = create(SIZE);
+        let (mut p, _c) = create(SIZE).unwrap();
 
         assert_eq!(p.write(&[0; SIZE]).unwrap(), SIZE);
-        match p.write(b"abc") {
-            Ok(_) => panic!(),
-            Err(err) => {
-                assert_eq!(err.kind(), ErrorKind::WouldBlock);
-                assert_eq!(err.get_ref().unwrap().description(), "Ring buffer is full");
-            }
-        }
+        let err = p.write(b"abc").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert_eq!(err.to_string(), "Ring buffer is full");
     }
 
     #[test]
     fn write_read_transmit() {
-        let (mut p, mut c) = create(16);
+        let (mut p, mut c) = create(16).unwrap();
 
         assert_eq!(p.write_transmit(&mut (&b"abcdef"[..]), None).unwrap(), 6);
 
@@ -658,7 +993,7 @@ mod test {
 
     #[test]
     fn write_read_transmit_concat() {
-        let (mut p, mut c) = create(16);
+        let (mut p, mut c) = create(16).unwrap();
 
         assert_eq!(p.write_transmit(&mut (&b"abc"[..]), None).unwrap(), 3);
         assert_eq!(p.write_transmit(&mut (&b"def"[..]), None).unwrap(), 3);
@@ -670,7 +1005,7 @@ mod test {
 
     #[test]
     fn write_read_transmit_split() {
-        let (mut p, mut c) = create(16);
+        let (mut p, mut c) = create(16).unwrap();
 
         assert_eq!(p.write_transmit(&mut (&b"abcdef"[..]), None).unwrap(), 6);
 
@@ -683,7 +1018,7 @@ mod test {
 
     #[test]
     fn read_transmit_block() {
-        let (_p, mut c) = create(16);
+        let (_p, mut c) = create(16).unwrap();
 
         let mut buf = vec!();
         match c.read_transmit(&mut buf, None) {
@@ -691,7 +1026,7 @@ mod test {
             Err(err) => match err {
                 TransmitError::This(e) => {
                     assert_eq!(e.kind(), ErrorKind::WouldBlock);
-                    assert_eq!(e.get_ref().unwrap().description(), "Ring buffer is empty");
+                    assert_eq!(e.to_string(), "Ring buffer is empty");
                 },
                 other => panic!("{:?}", other),
             }
@@ -701,7 +1036,7 @@ mod test {
     #[test]
     fn write_transmit_block() {
         const SIZE: usize = 16;
-        let (mut p, _c) = create(SIZE);
+        let (mut p, _c) = create(SIZE).unwrap();
 
         assert_eq!(p.write(&[0; SIZE]).unwrap(), SIZE);
         match p.write_transmit(&mut (&b"abc"[..]), None) {
@@ -709,7 +1044,7 @@ mod test {
             Err(err) => match err {
                 TransmitError::This(e) => {
                     assert_eq!(e.kind(), ErrorKind::WouldBlock);
-                    assert_eq!(e.get_ref().unwrap().description(), "Ring buffer is full");
+                    assert_eq!(e.to_string(), "Ring buffer is full");
                 },
                 other => panic!("{:?}", other),
             }
@@ -717,27 +1052,218 @@ mod test {
     }
 
     #[test]
-    fn close_cons() {
-        let (mut p, c) = create(16);
+    fn write_slices_commit() {
+        let (mut p, mut c) = create(8).unwrap();
+        let mut buf = [0; 8];
+
+        assert_eq!(p.write(b"abcdef").unwrap(), 6);
+        assert_eq!(c.read(&mut buf[0..4]).unwrap(), 4);
+
+        {
+            let (left, right) = p.write_slices();
+            assert_eq!(left.len() + right.len(), 6);
+            let data = b"ghijkl";
+            left.copy_from_slice(&data[..left.len()]);
+            let n = left.len();
+            right.copy_from_slice(&data[n..]);
+        }
+        p.commit(6).unwrap();
+
+        assert_eq!(p.write_slices().0.len(), 0);
+        assert_eq!(c.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf, b"efghijkl");
+    }
+
+    #[test]
+    fn read_slices_consume() {
+        let (mut p, mut c) = create(8).unwrap();
+        let mut buf = [0; 8];
 
+        assert_eq!(p.write(b"abcdef").unwrap(), 6);
+        assert_eq!(c.read(&mut buf[0..4]).unwrap(), 4);
+        assert_eq!(p.write(b"ghijkl").unwrap(), 6);
+
+        {
+            let (left, right) = c.read_slices();
+            assert_eq!([left, right].concat(), b"efghijkl");
+        }
+        c.consume(3).unwrap();
+
+        let (left, right) = c.read_slices();
+        assert_eq!([left, right].concat(), b"hijkl");
+    }
+
+    #[test]
+    #[should_panic]
+    fn commit_too_much() {
+        let (mut p, _c) = create(8).unwrap();
+        p.commit(9).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn consume_too_much() {
+        let (mut p, mut c) = create(8).unwrap();
         assert_eq!(p.write(b"abc").unwrap(), 3);
+        c.consume(4).unwrap();
+    }
 
-        (move || {
-            let _ = c;
-        })();
+    #[test]
+    fn buf_read_until() {
+        let (mut p, mut c) = create(8).unwrap();
+        let mut line = Vec::new();
 
-        match p.write(b"def") {
-            Ok(_) => panic!(),
-            Err(err) => {
-                assert_eq!(err.kind(), ErrorKind::BrokenPipe);
-                assert_eq!(err.get_ref().unwrap().description(), "Consumer was closed");
+        assert_eq!(p.write(b"abc\nde").unwrap(), 6);
+        assert_eq!(c.read_until(b'\n', &mut line).unwrap(), 4);
+        assert_eq!(&line, b"abc\n");
+
+        line.clear();
+        assert_eq!(c.read_until(b'\n', &mut line).unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(&line, b"de");
+
+        assert_eq!(p.write(b"fgh\n").unwrap(), 4);
+        assert_eq!(c.read_until(b'\n', &mut line).unwrap(), 4);
+        assert_eq!(&line, b"defgh\n");
+    }
+
+    #[test]
+    fn buf_read_lines() {
+        let (mut p, c) = create(16).unwrap();
+
+        assert_eq!(p.write(b"abc\ndef\n").unwrap(), 8);
+        drop(p);
+
+        let mut lines = c.lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "abc");
+        assert_eq!(lines.next().unwrap().unwrap(), "def");
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn write_read_vectored() {
+        let (mut p, mut c) = create(8).unwrap();
+        let mut buf = [0; 4];
+
+        assert_eq!(p.write(b"abcd").unwrap(), 4);
+        assert_eq!(c.read(&mut buf).unwrap(), 4);
+
+        let bufs = [IoSlice::new(b"ef"), IoSlice::new(b""), IoSlice::new(b"ghijkl")];
+        assert_eq!(p.write_vectored(&bufs).unwrap(), 8);
+        assert_eq!(p.write_vectored(&bufs).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        let (mut a, mut b) = ([0; 3], [0; 6]);
+        let mut bufs = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+        assert_eq!(c.read_vectored(&mut bufs).unwrap(), 8);
+        assert_eq!(c.read_vectored(&mut bufs).unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(&a, b"efg");
+        assert_eq!(&b[..5], b"hijkl");
+    }
+
+    #[test]
+    fn read_vectored_race() {
+        const LEN: usize = 0x4000;
+        let (mut p, mut c) = create(4).unwrap();
+
+        let jh = thread::spawn(move || {
+            let mut pos = 0;
+            while pos < LEN {
+                match p.write(&[1]) {
+                    Ok(n) => pos += n,
+                    Err(err) => {
+                        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                        thread::yield_now();
+                    },
+                }
+            }
+        });
+
+        let mut pos = 0;
+        let mut buf = [0; 2];
+        while pos < LEN {
+            match c.read_vectored(&mut [IoSliceMut::new(&mut buf)]) {
+                Ok(n) => { assert_ne!(n, 0); pos += n },
+                Err(err) => {
+                    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                    thread::yield_now();
+                },
             }
         }
+        jh.join().unwrap();
+        assert_eq!(c.read_vectored(&mut [IoSliceMut::new(&mut buf)]).unwrap(), 0);
+    }
+
+    #[test]
+    fn skip() {
+        let (mut p, mut c) = create(8).unwrap();
+        let mut buf = [0; 8];
+
+        assert_eq!(c.skip(2).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        assert_eq!(p.write(b"abcdef").unwrap(), 6);
+        assert_eq!(c.skip(2).unwrap(), 2);
+        assert_eq!(c.read(&mut buf[0..2]).unwrap(), 2);
+        assert_eq!(&buf[0..2], b"cd");
+
+        assert_eq!(p.write(b"ghij").unwrap(), 4);
+        assert_eq!(c.skip(3).unwrap(), 3);
+        assert_eq!(c.skip(8).unwrap(), 3);
+        assert_eq!(c.skip(1).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        drop(p);
+        assert_eq!(c.skip(1).unwrap(), 0);
+    }
+
+    #[test]
+    fn peek() {
+        let (mut p, mut c) = create(8).unwrap();
+        let mut buf = [0; 8];
+
+        assert_eq!(c.peek(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        assert_eq!(p.write(b"abcdef").unwrap(), 6);
+        assert_eq!(c.read(&mut buf[0..4]).unwrap(), 4);
+        assert_eq!(p.write(b"ghij").unwrap(), 4);
+
+        assert_eq!(c.peek(&mut buf[0..3]).unwrap(), 3);
+        assert_eq!(&buf[0..3], b"efg");
+        assert_eq!(c.peek_at(1, &mut buf).unwrap(), 5);
+        assert_eq!(&buf[0..5], b"fghij");
+        assert_eq!(c.peek_at(3, &mut buf[0..2]).unwrap(), 2);
+        assert_eq!(&buf[0..2], b"hi");
+        assert_eq!(c.peek_at(6, &mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        assert_eq!(c.read(&mut buf).unwrap(), 6);
+        assert_eq!(&buf[0..6], b"efghij");
+    }
+
+    #[test]
+    fn peek_closed() {
+        let (mut p, c) = create(8).unwrap();
+        let mut buf = [0; 8];
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        drop(p);
+
+        assert_eq!(c.peek_at(2, &mut buf).unwrap(), 1);
+        assert_eq!(c.peek_at(3, &mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn close_cons() {
+        let (mut p, c) = create(16).unwrap();
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+
+        drop(c);
+
+        let err = p.write(b"def").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        assert_eq!(err.to_string(), "Consumer was closed");
     }
 
     #[test]
     fn close_prod() {
-        let (mut p, mut c) = create(16);
+        let (mut p, mut c) = create(16).unwrap();
         let mut buf = [0; 6];
 
         assert_eq!(p.write(b"abcdef").unwrap(), 6);
@@ -745,30 +1271,258 @@ mod test {
         assert_eq!(c.read(&mut buf[0..3]).unwrap(), 3);
         assert_eq!(&buf[0..3], b"abc");
 
-        (move || {
-            let _ = p;
-        })();
+        drop(p);
 
         assert_eq!(c.read(&mut buf).unwrap(), 3);
         assert_eq!(&buf[0..3], b"def");
 
-        match c.read(&mut buf) {
-            Ok(_) => panic!(),
-            Err(err) => {
-                assert_eq!(err.kind(), ErrorKind::BrokenPipe);
-                assert_eq!(err.get_ref().unwrap().description(), "Producer was closed");
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn close_prod_broken_pipe() {
+        let (mut p, mut c) = create(16).unwrap();
+        let mut buf = [0; 6];
+
+        assert_eq!(c.eof_mode(), EofMode::Eof);
+        c.set_eof_mode(EofMode::BrokenPipe);
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        drop(p);
+
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        let err = c.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        assert_eq!(err.to_string(), "Producer was closed");
+        assert_eq!(c.skip(1).unwrap_err().kind(), ErrorKind::BrokenPipe);
+        assert_eq!(c.fill_buf().unwrap_err().kind(), ErrorKind::BrokenPipe);
+        match c.read_transmit(&mut vec!(), None) {
+            Err(TransmitError::This(e)) => assert_eq!(e.kind(), ErrorKind::BrokenPipe),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_to_end() {
+        let (mut p, mut c) = create(16).unwrap();
+        let mut data = Vec::new();
+
+        assert_eq!(p.write(b"abcdef").unwrap(), 6);
+        drop(p);
+
+        assert_eq!(c.read_to_end(&mut data).unwrap(), 6);
+        assert_eq!(&data, b"abcdef");
+    }
+
+    #[test]
+    fn read_transmit_eof() {
+        let (mut p, mut c) = create(16).unwrap();
+        let mut data = Vec::new();
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        drop(p);
+
+        assert_eq!(c.read_transmit(&mut data, None).unwrap(), 3);
+        assert_eq!(c.read_transmit(&mut data, None).unwrap(), 0);
+        assert_eq!(&data, b"abc");
+    }
+
+    #[test]
+    fn drop_unwinding() {
+        let (mut p, c) = create(16).unwrap();
+
+        let jh = thread::spawn(move || {
+            let _c = c;
+            panic!("Consumer thread failed");
+        });
+        assert!(jh.join().is_err());
+
+        let err = p.write(b"abc").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        assert_eq!(err.to_string(), "Consumer was closed");
+    }
+
+    #[test]
+    fn state() {
+        let (mut p, mut c) = create(16).unwrap();
+
+        assert_eq!(p.state(), FifoState::Open);
+        assert_eq!(c.state(), FifoState::Open);
+        assert!(!p.is_peer_closed());
+        assert!(!c.is_peer_closed());
+
+        p.close().unwrap();
+        assert_eq!(p.state(), FifoState::ProducerClosed);
+        assert_eq!(c.state(), FifoState::ProducerClosed);
+        assert!(!p.is_peer_closed());
+        assert!(c.is_peer_closed());
+
+        c.close().unwrap();
+        assert_eq!(p.state(), FifoState::Both);
+        assert!(p.is_peer_closed());
+
+        let (p, c) = create(16).unwrap();
+        drop(c);
+        assert_eq!(p.state(), FifoState::ConsumerClosed);
+        assert!(p.is_peer_closed());
+    }
+
+    #[test]
+    fn drop_prod_panicking() {
+        let (mut p, mut c) = create(16).unwrap();
+        let mut buf = [0; 6];
+
+        let jh = thread::spawn(move || {
+            assert_eq!(p.write(b"abc").unwrap(), 3);
+            panic!("Producer thread failed");
+        });
+        assert!(jh.join().is_err());
+
+        assert!(c.is_abnormally_closed());
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        let err = c.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), "Producer panicked");
+
+        let (p, c) = create(16).unwrap();
+        drop(p);
+        assert!(!c.is_abnormally_closed());
+    }
+
+    #[test]
+    fn lost_bytes() {
+        let (mut p, mut c) = create(16).unwrap();
+        let mut buf = [0; 2];
+
+        assert_eq!(p.write(b"abcdef").unwrap(), 6);
+        assert_eq!(c.read(&mut buf).unwrap(), 2);
+        assert_eq!(p.lost_bytes(), 0);
+
+        c.close().unwrap();
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+        assert_eq!(p.lost_bytes(), 4);
+        drop(c);
+        assert_eq!(p.lost_bytes(), 4);
+    }
+
+    #[test]
+    fn totals() {
+        let (mut p, mut c) = create(4).unwrap();
+        let mut buf = [0; 3];
+
+        for _ in 0..3 {
+            assert_eq!(p.write(b"abc").unwrap(), 3);
+            assert_eq!(c.read(&mut buf[0..2]).unwrap(), 2);
+            assert_eq!(c.skip(1).unwrap(), 1);
+        }
+        assert_eq!(p.write(b"defgh").unwrap(), 4);
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+
+        assert_eq!(p.total_written(), 13);
+        assert_eq!(c.total_written(), 13);
+        assert_eq!(p.total_read(), 12);
+        assert_eq!(c.total_read(), 12);
+    }
+
+    #[test]
+    fn totals_ordering() {
+        const LEN: u64 = 0x4000;
+        let (mut p, mut c) = create(4).unwrap();
+
+        let jh = thread::spawn(move || {
+            while p.total_written() < LEN {
+                if p.write(b"ab").is_err() {
+                    thread::yield_now();
+                }
             }
+        });
+
+        while c.total_read() < LEN {
+            if c.read(&mut [0; 3]).is_err() {
+                thread::yield_now();
+            }
+            let read = c.total_read();
+            assert!(read <= c.total_written());
+        }
+        jh.join().unwrap();
+    }
+
+    #[test]
+    fn drop_prod_panicking_undetected() {
+        let (p, mut c) = FifoBuilder::new(16).detect_panic(false).build().unwrap();
+
+        let jh = thread::spawn(move || {
+            let _p = p;
+            panic!("Producer thread failed");
+        });
+        assert!(jh.join().is_err());
+
+        assert!(!c.is_abnormally_closed());
+        assert_eq!(c.read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn close_prod_explicit() {
+        let (mut p, mut c) = create(16).unwrap();
+        let mut buf = [0; 6];
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        p.close().unwrap();
+        p.close().unwrap();
+
+        let err = p.write(b"def").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        assert_eq!(err.to_string(), "Producer was closed");
+
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn close_prod_error() {
+        let (mut p, mut c) = create(16).unwrap();
+        let mut buf = [0; 6];
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        p.close_with_error(Error::new(ErrorKind::InvalidData, "Bad frame")).unwrap();
+        p.close_with_error(Error::new(ErrorKind::TimedOut, "Ignored")).unwrap();
+
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        for _ in 0..2 {
+            let err = c.read(&mut buf).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert_eq!(err.to_string(), "Bad frame");
         }
+
+        c.close().unwrap();
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn close_cons_explicit() {
+        let (mut p, mut c) = create(16).unwrap();
+
+        c.close().unwrap();
+
+        let err = p.write(b"abc").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        assert_eq!(err.to_string(), "Consumer was closed");
+
+        c.set_eof_mode(EofMode::BrokenPipe);
+        let err = c.read(&mut [0; 4]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        assert_eq!(err.to_string(), "Consumer was closed");
     }
 
     #[test]
     fn poll_cons() {
-        let (mut p, mut c) = create(16);
-        let poll = Poll::new().unwrap();
+        let (mut p, mut c) = create(16).unwrap();
+        let mut poll = Poll::new().unwrap();
         let mut events = Events::with_capacity(16);
         let mut buf = [0; 6];
 
-        poll.register(&c, Token(0), Ready::readable(), PollOpt::edge()).unwrap();
+        poll.registry().register(&mut c, Token(0), Interest::READABLE).unwrap();
 
         let jh = thread::spawn(move || {
             thread::sleep(Duration::from_millis(10));
@@ -785,7 +1539,7 @@ mod test {
 
             let event = eiter.next().unwrap();
             assert_eq!(event.token().0, 0);
-            assert!(event.readiness().is_readable());
+            assert!(event.is_readable());
             assert_eq!(c.read(&mut buf).unwrap(), 6);
             assert_eq!(&buf, b"abcdef");
 
@@ -801,11 +1555,11 @@ mod test {
     #[test]
     fn poll_prod() {
         const SIZE: usize = 16;
-        let (mut p, mut c) = create(SIZE);
-        let poll = Poll::new().unwrap();
+        let (mut p, mut c) = create(SIZE).unwrap();
+        let mut poll = Poll::new().unwrap();
         let mut events = Events::with_capacity(16);
 
-        poll.register(&p, Token(0), Ready::writable(), PollOpt::edge()).unwrap();
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
 
         assert_eq!(p.write(&[0; SIZE]).unwrap(), SIZE);
 
@@ -826,7 +1580,7 @@ mod test {
             let event = eiter.next().unwrap();
             assert_eq!(event.token().0, 0);
 
-            assert!(event.readiness().is_writable());
+            assert!(event.is_writable());
 
             assert_eq!(p.write(b"abcdefghi").unwrap(), 6);
 
@@ -841,36 +1595,23 @@ mod test {
 
     #[test]
     fn poll_cons_close() {
-        let (p, mut c) = create(16);
-        let poll = Poll::new().unwrap();
+        let (p, mut c) = create(16).unwrap();
+        let mut poll = Poll::new().unwrap();
         let mut events = Events::with_capacity(16);
         let mut buf = [0; 3];
 
-        poll.register(&c, Token(0), Ready::readable(), PollOpt::edge()).unwrap();
+        poll.registry().register(&mut c, Token(0), Interest::READABLE).unwrap();
 
         let jh = thread::spawn(move || {
             thread::sleep(Duration::from_millis(10));
             let _ = p;
         });
 
-        'outer: for _ in 0..2 {
-            poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
-
-            for event in events.iter() {
-                assert_eq!(event.token().0, 0);
-                assert!(event.readiness().is_readable());
-                match c.read(&mut buf) {
-                    Ok(_) => panic!(),
-                    Err(err) => {
-                        match err.kind() {
-                            ErrorKind::BrokenPipe => break 'outer,
-                            ErrorKind::WouldBlock => (),
-                            _ => panic!("{:?}", err),
-                        }
-                    }
-                }
-            }
-        }
+        poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(event.token().0, 0);
+        assert!(event.is_read_closed());
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
 
         jh.join().unwrap();
     }
@@ -878,11 +1619,11 @@ mod test {
     #[test]
     fn poll_prod_close() {
         const SIZE: usize = 16;
-        let (mut p, c) = create(SIZE);
-        let poll = Poll::new().unwrap();
+        let (mut p, c) = create(SIZE).unwrap();
+        let mut poll = Poll::new().unwrap();
         let mut events = Events::with_capacity(16);
 
-        poll.register(&p, Token(0), Ready::writable(), PollOpt::edge()).unwrap();
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
 
         assert_eq!(p.write(&[0; SIZE]).unwrap(), SIZE);
 
@@ -891,56 +1632,319 @@ mod test {
             let _ = c;
         });
 
-        'outer: loop {
-            poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+        poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(event.token().0, 0);
+        assert!(event.is_write_closed());
+        assert_eq!(p.write(b"def").unwrap_err().kind(), ErrorKind::BrokenPipe);
 
-            for event in events.iter() {
-                assert_eq!(event.token().0, 0);
-                assert!(event.readiness().is_writable());
-                match p.write(b"def") {
-                    Ok(_) => panic!(),
-                    Err(err) => {
-                        match err.kind() {
-                            ErrorKind::BrokenPipe => break 'outer,
-                            ErrorKind::WouldBlock => (),
-                            _ => panic!("{:?}", err),
-                        }
-                    }
-                }
-            }
+        jh.join().unwrap();
+    }
+
+    #[test]
+    fn poll_close_explicit() {
+        let (mut p, mut c) = create(16).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        poll.registry().register(&mut c, Token(1), Interest::READABLE).unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(10))).unwrap();
+
+        p.close().unwrap();
+        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(event.token(), Token(1));
+        assert!(event.is_read_closed());
+
+        c.close().unwrap();
+        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(event.token(), Token(0));
+        assert!(event.is_write_closed());
+
+        poll.registry().deregister(&mut p).unwrap();
+        poll.registry().deregister(&mut c).unwrap();
+    }
+
+    fn poll_token(poll: &mut Poll, events: &mut Events) -> Option<Token> {
+        poll.poll(events, Some(Duration::from_millis(10))).unwrap();
+        events.iter().next().map(|e| e.token())
+    }
+
+    #[test]
+    fn level_cons() {
+        let (mut p, mut c) = create(16).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let mut buf = [0; 6];
+
+        poll.registry().register(&mut c, Token(0), Interest::READABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        assert_eq!(p.write(b"abcdef").unwrap(), 6);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(c.read(&mut buf[0..3]).unwrap(), 3);
+        poll.registry().reregister(&mut c, Token(0), Interest::READABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        poll.registry().reregister(&mut c, Token(0), Interest::READABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        poll.registry().reregister(&mut c, Token(0), Interest::READABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+    }
+
+    #[test]
+    fn level_prod() {
+        const SIZE: usize = 16;
+        let (mut p, mut c) = create(SIZE).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let mut buf = [0; 3];
+
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(p.write(&[0; SIZE - 3]).unwrap(), SIZE - 3);
+        poll.registry().reregister(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(p.write(&[0; 3]).unwrap(), 3);
+        poll.registry().reregister(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        poll.registry().reregister(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+    }
+
+    #[test]
+    fn edge_cons() {
+        let (mut p, mut c) = create(16).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let mut buf = [0; 6];
+
+        poll.registry().register(&mut c, Token(0), Interest::READABLE).unwrap();
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(p.write(b"def").unwrap(), 3);
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        assert_eq!(c.read(&mut buf).unwrap(), 6);
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+    }
+
+    #[test]
+    fn edge_prod() {
+        const SIZE: usize = 16;
+        let (mut p, mut c) = create(SIZE).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let mut buf = [0; 3];
+
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(p.write(&[0; SIZE]).unwrap(), SIZE);
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        assert_eq!(p.write(&[0; 6]).unwrap(), 6);
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+    }
+
+    #[test]
+    fn poll_slices() {
+        const SIZE: usize = 16;
+        let (mut p, mut c) = create(SIZE).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        poll.registry().register(&mut c, Token(1), Interest::READABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        let n = p.write_slices().0.len();
+        p.commit(n).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(1)));
+
+        let n = c.read_slices().0.len();
+        c.consume(n).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+    }
+
+    #[test]
+    fn poll_buf_read() {
+        const SIZE: usize = 8;
+        let (mut p, mut c) = create(SIZE).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(p.write(b"abc\ndefg").unwrap(), SIZE);
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        let mut line = String::new();
+        assert_eq!(c.read_line(&mut line).unwrap(), 4);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+    }
+
+    #[test]
+    fn poll_vectored() {
+        const SIZE: usize = 8;
+        let (mut p, mut c) = create(SIZE).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        poll.registry().register(&mut c, Token(1), Interest::READABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        let bufs = [IoSlice::new(b"abc"), IoSlice::new(b"defgh")];
+        assert_eq!(p.write_vectored(&bufs).unwrap(), SIZE);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(1)));
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        let (mut a, mut b) = ([0; 2], [0; 2]);
+        let mut bufs = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+        assert_eq!(c.read_vectored(&mut bufs).unwrap(), 4);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+    }
+
+    #[test]
+    fn poll_skip() {
+        const SIZE: usize = 8;
+        let (mut p, mut c) = create(SIZE).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(p.write(&[1; SIZE]).unwrap(), SIZE);
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        assert_eq!(c.skip(3).unwrap(), 3);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+        assert_eq!(p.write(&[2; SIZE]).unwrap(), 3);
+    }
+
+    #[test]
+    fn poll_peek() {
+        const SIZE: usize = 8;
+        let (mut p, c) = create(SIZE).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let mut buf = [0; SIZE];
+
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(p.write(&[1; SIZE]).unwrap(), SIZE);
+        assert_eq!(c.peek(&mut buf).unwrap(), SIZE);
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+        assert_eq!(p.write(b"a").unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn watermarks_invalid() {
+        for &(low, high) in [(0, 1), (1, 0), (9, 1), (1, 9)].iter() {
+            let err = create_with(8, Watermarks { low, high }).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
         }
+    }
 
-        jh.join().unwrap();
+    #[test]
+    fn poll_low_watermark() {
+        let (mut p, mut c) = create_with(8, Watermarks { low: 4, high: 1 }).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let mut buf = [0; 2];
+
+        poll.registry().register(&mut c, Token(0), Interest::READABLE).unwrap();
+
+        assert_eq!(p.write(b"ab").unwrap(), 2);
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+        assert_eq!(p.write(b"cd").unwrap(), 2);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(c.read(&mut buf[0..1]).unwrap(), 1);
+        poll.registry().reregister(&mut c, Token(0), Interest::READABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+        assert_eq!(c.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf, b"bc");
+
+        drop(p);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+    }
+
+    #[test]
+    fn poll_high_watermark() {
+        const SIZE: usize = 8;
+        let (mut p, mut c) = create_with(SIZE, Watermarks { low: 1, high: 4 }).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let mut buf = [0; 2];
+
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
+
+        assert_eq!(p.write(&[0; 5]).unwrap(), 5);
+        poll.registry().reregister(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+
+        assert_eq!(p.write(&[0; SIZE]).unwrap(), 3);
+        assert_eq!(c.read(&mut buf).unwrap(), 2);
+        assert_eq!(poll_token(&mut poll, &mut events), None);
+        assert_eq!(c.read(&mut buf).unwrap(), 2);
+        assert_eq!(poll_token(&mut poll, &mut events), Some(Token(0)));
     }
 
     #[test]
     fn poll_prod_cons() {
         const SIZE: usize = 16;
-        let (mut p, mut c) = create(SIZE);
+        let (mut p, mut c) = create(SIZE).unwrap();
+        let data = [vec![0; SIZE], vec![1; SIZE/2]].concat();
+        let sent = data.clone();
 
         let cjh = thread::spawn(move || {
-            let poll = Poll::new().unwrap();
+            let mut poll = Poll::new().unwrap();
             let mut events = Events::with_capacity(16);
             let mut buf = [0; SIZE/2];
+            let mut data = Vec::new();
 
-            poll.register(&c, Token(0), Ready::readable(), PollOpt::edge() | PollOpt::oneshot()).unwrap();
+            poll.registry().register(&mut c, Token(0), Interest::READABLE).unwrap();
             
-            let mut i = 0;
             'outer: loop {
                 poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
                 for event in events.iter() {
                     assert_eq!(event.token().0, 0);
-                    assert!(event.readiness().is_readable());
+                    assert!(event.is_readable() || event.is_read_closed());
                     'inner: loop {
                         match c.read(&mut buf) {
-                            Ok(n) => {
-                                assert_eq!(n, SIZE/2);
-                                assert_eq!(&buf, &[i/2; SIZE/2]);
-                                i += 1;
-                            },
+                            Ok(0) => break 'outer,
+                            Ok(n) => data.extend_from_slice(&buf[..n]),
                             Err(err) => {
                                 match err.kind() {
-                                    ErrorKind::BrokenPipe => break 'outer,
                                     ErrorKind::WouldBlock => break 'inner,
                                     _ => panic!("{:?}", err),
                                 }
@@ -948,30 +1952,92 @@ mod test {
                         }
                     }
                 }
-                poll.reregister(&c, Token(0), Ready::readable(), PollOpt::edge() | PollOpt::oneshot()).unwrap();
+                poll.registry().reregister(&mut c, Token(0), Interest::READABLE).unwrap();
             }
-            assert_eq!(i, 3);
+            data
         });
 
         let pjh = thread::spawn(move || {
-            let poll = Poll::new().unwrap();
+            let mut poll = Poll::new().unwrap();
             let mut events = Events::with_capacity(16);
+            let mut pos = 0;
 
-            assert_eq!(p.write(&[0; SIZE]).unwrap(), SIZE);
-            poll.register(&p, Token(0), Ready::writable(), PollOpt::edge() | PollOpt::oneshot()).unwrap();
-            poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+            poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
 
-            let event = events.iter().next().unwrap();
-            assert_eq!(event.token().0, 0);
-            assert!(event.readiness().is_writable());
-            assert_eq!(p.write(&[1; SIZE/2]).unwrap(), SIZE/2);
-            poll.reregister(&p, Token(0), Ready::writable(), PollOpt::edge() | PollOpt::oneshot()).unwrap();
-            poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+            'outer: loop {
+                poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+                for event in events.iter() {
+                    assert_eq!(event.token().0, 0);
+                    assert!(event.is_writable());
+                    loop {
+                        match p.write(&sent[pos..]) {
+                            Ok(n) => {
+                                pos += n;
+                                if pos == sent.len() {
+                                    break 'outer;
+                                }
+                            },
+                            Err(err) => {
+                                assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        });
 
-            thread::sleep(Duration::from_millis(10));
+        pjh.join().unwrap();
+        assert_eq!(cjh.join().unwrap(), data);
+    }
+
+    #[test]
+    fn poll_prod_cons_many() {
+        const SIZE: usize = 16;
+        const LEN: usize = 0x10000;
+        let (mut p, mut c) = create(SIZE).unwrap();
+        let data: Vec<u8> = (0..LEN).map(|i| i as u8).collect();
+        let sent = data.clone();
+
+        let pjh = thread::spawn(move || {
+            let mut poll = Poll::new().unwrap();
+            let mut events = Events::with_capacity(16);
+            let mut pos = 0;
+
+            poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+            while pos < LEN {
+                match p.write(&sent[pos..(pos + 7).min(LEN)]) {
+                    Ok(n) => pos += n,
+                    Err(err) => {
+                        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                        poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+                        assert!(!events.is_empty());
+                    }
+                }
+            }
         });
 
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let mut buf = [0; 5];
+        let mut received = Vec::new();
+
+        poll.registry().register(&mut c, Token(0), Interest::READABLE).unwrap();
+        loop {
+            match c.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => received.extend_from_slice(&buf[..n]),
+                Err(err) => match err.kind() {
+                    ErrorKind::WouldBlock => {
+                        poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+                        assert!(!events.is_empty());
+                    },
+                    _ => panic!("{:?}", err),
+                }
+            }
+        }
+
         pjh.join().unwrap();
-        cjh.join().unwrap();
+        assert_eq!(received, data);
     }
 }
@@ -0,0 +1,233 @@
+//! Multi-producer FIFO, the producer can be cloned and shared between threads.
+
+use std::io::{Write, IoSlice, Error, ErrorKind};
+use std::fmt;
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+
+use mio::{Registry, Token, Interest, event::Source, unix::SourceFd};
+
+use crate::{FifoBuilder, Watermarks, Producer, Consumer};
+use crate::signal::PipeWriter;
+
+/// Producer that can be cloned.
+///
+/// Writes of up to [`atomic_size`](Self::atomic_size) bytes are either done as a whole
+/// or refused with [`ErrorKind::WouldBlock`], so they never interleave with writes of other clones.
+/// Larger writes may be partial.
+///
+/// The producer becomes writable once there is enough free space for an atomic write.
+/// The consumer sees closure only when the last clone is dropped.
+pub struct SharedProducer {
+    inner: Arc<Mutex<Producer>>,
+    reg: PipeWriter,
+    atomic: usize,
+}
+
+/// Creates a multi-producer FIFO.
+///
+/// `atomic` is the largest size of a write that doesn't interleave with others,
+/// it must be within `1..=capacity`.
+pub fn create_mpsc(capacity: usize, atomic: usize) -> Result<(SharedProducer, Consumer), Error> {
+    if atomic == 0 || atomic > capacity {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Atomic write size is out of range",
+        ))
+    }
+    let wm = Watermarks { low: 1, high: atomic };
+    let (prod, cons) = FifoBuilder::new(capacity).watermarks(wm).build()?;
+    let reg = prod.reg.try_clone()?;
+    Ok((SharedProducer { inner: Arc::new(Mutex::new(prod)), reg, atomic }, cons))
+}
+
+impl SharedProducer {
+    /// Returns the largest size of a write that doesn't interleave with others.
+    pub fn atomic_size(&self) -> usize {
+        self.atomic
+    }
+
+    /// Creates another producer of the same FIFO.
+    ///
+    /// Fails if the readiness descriptor cannot be duplicated.
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Ok(Self { inner: self.inner.clone(), reg: self.reg.try_clone()?, atomic: self.atomic })
+    }
+
+    /// Locks the underlying producer, other clones cannot write until the guard is dropped.
+    ///
+    /// Writes through the guard are plain [`Producer`] writes, they bypass the atomic write check,
+    /// so even a write of up to [`atomic_size`](Self::atomic_size) bytes may be partial.
+    pub fn lock(&self) -> MutexGuard<'_, Producer> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Writes `len` bytes with `op` if it is not an atomic write that doesn't fit.
+    fn write_with<F>(&self, len: usize, op: F) -> Result<usize, Error>
+    where F: FnOnce(&mut Producer) -> Result<usize, Error> {
+        let mut prod = self.lock();
+        if len <= self.atomic && prod.rbp.remaining() < len {
+            if let Some(e) = prod.closed_error() {
+                return Err(e);
+            }
+            return Err(Error::new(
+                ErrorKind::WouldBlock,
+                "Ring buffer is full",
+            ))
+        }
+        op(&mut prod)
+    }
+}
+
+impl Clone for SharedProducer {
+    /// # Panics
+    ///
+    /// Panics if the readiness descriptor cannot be duplicated, see [`try_clone`](Self::try_clone).
+    fn clone(&self) -> Self {
+        self.try_clone().expect("Cannot clone producer")
+    }
+}
+
+impl fmt::Debug for SharedProducer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("SharedProducer");
+        // Formatting must not block or deadlock while the producer is locked, e.g. by `lock`.
+        match self.inner.try_lock() {
+            Ok(prod) => d.field("inner", &*prod),
+            Err(TryLockError::Poisoned(e)) => d.field("inner", &*e.into_inner()),
+            Err(TryLockError::WouldBlock) => d.field("inner", &format_args!("<locked>")),
+        };
+        d.field("atomic", &self.atomic).finish()
+    }
+}
+
+impl Source for SharedProducer {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        SourceFd(&self.reg.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        SourceFd(&self.reg.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> Result<(), Error> {
+        SourceFd(&self.reg.as_raw_fd()).deregister(registry)
+    }
+}
+
+impl Write for SharedProducer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.write_with(buf.len(), |p| p.write(buf))
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> Result<usize, Error> {
+        let len = bufs.iter().map(|b| b.len()).sum();
+        self.write_with(len, |p| p.write_vectored(bufs))
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.lock().flush()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Read;
+    use std::thread;
+    use std::time::Duration;
+
+    use mio::{Poll, Events};
+
+    #[test]
+    fn atomic() {
+        let (mut p, mut c) = create_mpsc(8, 4).unwrap();
+        let mut q = p.clone();
+        let mut buf = [0; 8];
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        assert_eq!(q.write(b"def").unwrap(), 3);
+        assert_eq!(p.write(b"ghi").unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(q.write(b"gh").unwrap(), 2);
+        assert_eq!(q.write(b"ijklmn").unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        assert_eq!(c.read(&mut buf[0..3]).unwrap(), 3);
+        assert_eq!(q.write(b"ijklmn").unwrap(), 3);
+        assert_eq!(c.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf, b"defghijk");
+
+        for atomic in [0, 9] {
+            let err = create_mpsc(8, atomic).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            assert_eq!(err.to_string(), "Atomic write size is out of range");
+        }
+    }
+
+    #[test]
+    fn debug_locked() {
+        let (p, _c) = create_mpsc(4, 2).unwrap();
+
+        assert!(!format!("{:?}", p).contains("<locked>"));
+        let _guard = p.lock();
+        assert!(format!("{:?}", p).contains("inner: <locked>"));
+    }
+
+    #[test]
+    fn close() {
+        let (p, mut c) = create_mpsc(8, 4).unwrap();
+        let mut q = p.clone();
+        let mut buf = [0; 8];
+
+        drop(p);
+        assert!(!c.is_peer_closed());
+        assert_eq!(q.write(b"abc").unwrap(), 3);
+        drop(q);
+        assert!(c.is_peer_closed());
+
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn poll_many() {
+        const THREADS: usize = 4;
+        const MESSAGES: usize = 100;
+        let (p, c) = create_mpsc(16, 5).unwrap();
+
+        let jhs = (0..THREADS).map(|i| {
+            let mut p = p.clone();
+            thread::spawn(move || {
+                let mut poll = Poll::new().unwrap();
+                let mut events = Events::with_capacity(4);
+                poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+                let msg = [b'a' + i as u8; 5];
+                for _ in 0..MESSAGES {
+                    loop {
+                        match p.write(&msg) {
+                            Ok(n) => { assert_eq!(n, 5); break },
+                            Err(err) => {
+                                assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                                poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+                                assert!(!events.is_empty());
+                            },
+                        }
+                    }
+                }
+            })
+        }).collect::<Vec<_>>();
+        drop(p);
+
+        let mut received = Vec::new();
+        c.into_blocking().read_to_end(&mut received).unwrap();
+        for jh in jhs {
+            jh.join().unwrap();
+        }
+
+        assert_eq!(received.len(), THREADS * MESSAGES * 5);
+        for msg in received.chunks(5) {
+            assert!(msg.iter().all(|&b| b == msg[0]));
+        }
+    }
+}
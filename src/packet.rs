@@ -0,0 +1,335 @@
+//! Message-oriented FIFO, an in-process equivalent of `SOCK_SEQPACKET`.
+
+use std::io::{Write, Read, IoSlice, Error, ErrorKind};
+use std::{iter, mem};
+
+use mio::{Registry, Token, Interest, event::Source};
+
+use crate::{copy_slices, FifoBuilder, FifoState, Watermarks, Producer, Consumer};
+
+/// Size of the length prefix stored in front of each packet.
+const HEADER: usize = mem::size_of::<u32>();
+
+/// Producer that writes whole packets.
+///
+/// Each write is a single packet, it is either written as a whole or refused with [`ErrorKind::WouldBlock`].
+/// Packets are never empty, so reading `Ok(0)` into a non-empty buffer always means end of stream.
+/// The producer becomes writable once there is enough free space for a packet of [`max_packet_size`](Self::max_packet_size).
+#[derive(Debug)]
+pub struct PacketProducer {
+    inner: Producer,
+    max: usize,
+}
+
+/// Consumer that reads whole packets.
+///
+/// Each read returns a single packet. The consumer becomes readable once at least one packet is buffered.
+#[derive(Debug)]
+pub struct PacketConsumer {
+    inner: Consumer,
+}
+
+/// Creates a packet FIFO that holds up to `capacity` bytes including a 4-byte length prefix of each packet.
+///
+/// The largest packet is `capacity - 4` bytes long, so `capacity` must be greater than 4.
+pub fn create_packet(capacity: usize) -> Result<(PacketProducer, PacketConsumer), Error> {
+    if capacity <= HEADER {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Capacity is too small",
+        ))
+    }
+    let max = (capacity - HEADER).min(u32::MAX as usize);
+    let wm = Watermarks { low: 1, high: max + HEADER };
+    let (prod, cons) = FifoBuilder::new(capacity).watermarks(wm).build()?;
+    Ok((PacketProducer { inner: prod, max }, PacketConsumer { inner: cons }))
+}
+
+impl PacketProducer {
+    /// Returns the size of the largest packet that can be written.
+    pub fn max_packet_size(&self) -> usize {
+        self.max
+    }
+
+    /// Returns the underlying byte producer.
+    pub fn get_ref(&self) -> &Producer {
+        &self.inner
+    }
+
+    /// Closes the producer without dropping it, see [`Producer::close`].
+    pub fn close(&mut self) -> Result<(), Error> {
+        self.inner.close()
+    }
+
+    /// Returns which ends of the FIFO are closed.
+    pub fn state(&self) -> FifoState {
+        self.inner.state()
+    }
+
+    /// Writes a packet of `len` bytes gathered from `bufs`.
+    fn write_packet(&mut self, bufs: &[&[u8]], len: usize) -> Result<usize, Error> {
+        if let Some(e) = self.inner.closed_error() {
+            return Err(e);
+        }
+        // An empty packet would be read as `Ok(0)`, which means end of stream.
+        if len == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Packet is empty",
+            ))
+        }
+        if len > self.max {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Packet is too large",
+            ))
+        }
+        if self.inner.rbp.remaining() < HEADER + len {
+            return Err(Error::new(
+                ErrorKind::WouldBlock,
+                "Ring buffer is full",
+            ))
+        }
+
+        let header = (len as u32).to_le_bytes();
+        {
+            let (left, right) = self.inner.write_slices();
+            let src = iter::once(&header[..]).chain(bufs.iter().copied());
+            copy_slices(src, iter::once(left).chain(iter::once(right)));
+        }
+        // The whole packet is committed at once, so the consumer never sees a part of it.
+        self.inner.commit(HEADER + len).and(Ok(len))
+    }
+}
+
+impl PacketConsumer {
+    /// Returns the size of the next packet, `None` if no packet is buffered.
+    pub fn next_packet_size(&self) -> Option<usize> {
+        let mut header = [0; HEADER];
+        let (left, right) = self.inner.read_slices();
+        match copy_slices(iter::once(left).chain(iter::once(right)), iter::once(&mut header[..])) {
+            HEADER => Some(u32::from_le_bytes(header) as usize),
+            _ => None,
+        }
+    }
+
+    /// Returns the underlying byte consumer.
+    pub fn get_ref(&self) -> &Consumer {
+        &self.inner
+    }
+
+    /// Closes the consumer without dropping it, see [`Consumer::close`].
+    pub fn close(&mut self) -> Result<(), Error> {
+        self.inner.close()
+    }
+
+    /// Returns which ends of the FIFO are closed.
+    pub fn state(&self) -> FifoState {
+        self.inner.state()
+    }
+}
+
+impl Source for PacketProducer {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> Result<(), Error> {
+        self.inner.deregister(registry)
+    }
+}
+
+impl Source for PacketConsumer {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> Result<(), Error> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> Result<(), Error> {
+        self.inner.deregister(registry)
+    }
+}
+
+impl Write for PacketProducer {
+    /// Writes `buf` as a single packet.
+    ///
+    /// Fails with [`ErrorKind::InvalidInput`] if `buf` is empty or longer than [`max_packet_size`](Self::max_packet_size).
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.write_packet(&[buf], buf.len())
+    }
+
+    /// Writes all `bufs` as a single packet.
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> Result<usize, Error> {
+        let bufs = bufs.iter().map(|b| &**b).collect::<Vec<_>>();
+        let len = bufs.iter().map(|b| b.len()).sum();
+        self.write_packet(&bufs, len)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Read for PacketConsumer {
+    /// Reads a single packet into `buf`.
+    ///
+    /// As with `SOCK_SEQPACKET` the part of the packet that doesn't fit into `buf` is discarded,
+    /// use [`next_packet_size`](PacketConsumer::next_packet_size) to avoid that.
+    /// Reading into an empty `buf` returns `Ok(0)` and leaves the packet in place.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = match self.next_packet_size() {
+            Some(len) => len,
+            None => return match self.inner.empty_result(0) {
+                Some(res) => res.and(Ok(0)),
+                None => self.read(buf),
+            },
+        };
+        let num = len.min(buf.len());
+        self.inner.peek_at(HEADER, &mut buf[..num])?;
+        self.inner.consume(HEADER + len).and(Ok(num))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::thread;
+    use std::time::Duration;
+
+    use mio::{Poll, Events};
+
+    #[test]
+    fn write_read() {
+        let (mut p, mut c) = create_packet(18).unwrap();
+        let mut buf = [0; 8];
+
+        assert_eq!(p.max_packet_size(), 14);
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        let err = p.write(b"").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "Packet is empty");
+        assert_eq!(p.write_vectored(&[IoSlice::new(b"de"), IoSlice::new(b"f")]).unwrap(), 3);
+        assert_eq!(p.write(b"g").unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        assert_eq!(c.read(&mut []).unwrap(), 0);
+        assert_eq!(c.next_packet_size(), Some(3));
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[0..3], b"abc");
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[0..3], b"def");
+        assert_eq!(c.next_packet_size(), None);
+        assert_eq!(c.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn sizes() {
+        let (mut p, mut c) = create_packet(12).unwrap();
+        let mut buf = [0; 4];
+
+        let err = p.write(b"abcdefghi").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "Packet is too large");
+
+        assert_eq!(p.write(b"abcdefgh").unwrap(), 8);
+        assert_eq!(c.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"abcd");
+        assert_eq!(c.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        assert!(create_packet(4).is_err());
+    }
+
+    #[test]
+    fn close() {
+        let (mut p, mut c) = create_packet(16).unwrap();
+        let mut buf = [0; 8];
+
+        assert_eq!(p.write(b"abc").unwrap(), 3);
+        drop(p);
+        assert_eq!(c.read(&mut buf).unwrap(), 3);
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+
+        let (mut p, c) = create_packet(16).unwrap();
+        drop(c);
+        assert_eq!(p.write(b"abc").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn poll() {
+        let (mut p, mut c) = create_packet(12).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let timeout = Some(Duration::from_millis(10));
+
+        poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+        poll.registry().register(&mut c, Token(1), Interest::READABLE).unwrap();
+
+        poll.poll(&mut events, timeout).unwrap();
+        assert_eq!(events.iter().map(|e| e.token()).collect::<Vec<_>>(), [Token(0)]);
+
+        assert_eq!(p.write(b"ab").unwrap(), 2);
+        poll.poll(&mut events, timeout).unwrap();
+        assert_eq!(events.iter().map(|e| e.token()).collect::<Vec<_>>(), [Token(1)]);
+
+        assert_eq!(c.read(&mut [0; 8]).unwrap(), 2);
+        poll.poll(&mut events, timeout).unwrap();
+        assert_eq!(events.iter().map(|e| e.token()).collect::<Vec<_>>(), [Token(0)]);
+    }
+
+    #[test]
+    fn threads() {
+        const PACKETS: usize = 200;
+        let (mut p, mut c) = create_packet(32).unwrap();
+
+        let jh = thread::spawn(move || {
+            let mut poll = Poll::new().unwrap();
+            let mut events = Events::with_capacity(4);
+            poll.registry().register(&mut p, Token(0), Interest::WRITABLE).unwrap();
+            for i in 0..PACKETS {
+                let msg = vec![i as u8; 1 + i % 17];
+                loop {
+                    match p.write(&msg) {
+                        Ok(n) => { assert_eq!(n, msg.len()); break },
+                        Err(err) => {
+                            assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                            poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+                        },
+                    }
+                }
+            }
+        });
+
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        poll.registry().register(&mut c, Token(0), Interest::READABLE).unwrap();
+        let mut buf = [0; 32];
+        for i in 0..PACKETS {
+            loop {
+                match c.read(&mut buf) {
+                    Ok(n) => {
+                        assert_eq!(n, 1 + i % 17);
+                        assert!(buf[..n].iter().all(|&b| b == i as u8));
+                        break
+                    },
+                    Err(err) => {
+                        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+                        poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+                    },
+                }
+            }
+        }
+        jh.join().unwrap();
+        assert_eq!(c.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -0,0 +1,493 @@
+//! Readiness signals backed by non-blocking Unix socket pairs used as pipes.
+//!
+//! Every FIFO end owns one pipe end that is registered in [`Poll`](mio::Poll)
+//! and holds the opposite end of its peer's pipe, so each side can raise or
+//! clear the readiness of the other one.
+//!
+//! Sockets are used instead of `pipe(2)` because writing into them can suppress `SIGPIPE`,
+//! the peer may close its end at any moment and the host process may not ignore the signal.
+//!
+//! [`Readiness`] keeps the state of a pipe in sync with the state of the ring buffer
+//! when both sides try to change it concurrently.
+
+use std::io::{Read, Error, ErrorKind};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::{Condvar, Mutex, atomic::{fence, AtomicBool, AtomicUsize, Ordering}};
+use std::task::Waker;
+#[cfg(any(feature = "futures-io", feature = "tokio"))]
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+const CHUNK_SIZE: usize = 4096;
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const SEND_FLAGS: libc::c_int = 0;
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+const SEND_FLAGS: libc::c_int = libc::MSG_NOSIGNAL;
+
+/// Read end of a signal pipe. Readable while the pipe contains data.
+#[derive(Debug)]
+pub struct PipeReader {
+    sock: UnixStream,
+}
+
+/// Write end of a signal pipe. Writable while the pipe is not full.
+#[derive(Debug)]
+pub struct PipeWriter {
+    sock: UnixStream,
+}
+
+/// Creates a new non-blocking pipe.
+pub fn pipe() -> Result<(PipeReader, PipeWriter), Error> {
+    let (reader, writer) = UnixStream::pair()?;
+    reader.set_nonblocking(true)?;
+    writer.set_nonblocking(true)?;
+    // A small buffer makes filling the pipe cheap.
+    setsockopt(&writer, libc::SO_SNDBUF, CHUNK_SIZE as libc::c_int)?;
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    setsockopt(&writer, libc::SO_NOSIGPIPE, 1)?;
+    Ok((PipeReader { sock: reader }, PipeWriter { sock: writer }))
+}
+
+fn setsockopt(sock: &UnixStream, opt: libc::c_int, value: libc::c_int) -> Result<(), Error> {
+    let res = unsafe { libc::setsockopt(
+        sock.as_raw_fd(), libc::SOL_SOCKET, opt,
+        &value as *const libc::c_int as *const libc::c_void,
+        std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+    ) };
+    if res != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn is_closed(err: &Error) -> bool {
+    err.kind() == ErrorKind::BrokenPipe
+}
+
+impl PipeReader {
+    /// Removes all data from the pipe, so it stops being readable
+    /// and the write end becomes writable.
+    pub fn drain(&self) -> Result<(), Error> {
+        let mut buf = [0; CHUNK_SIZE];
+        loop {
+            match (&self.sock).read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(_) => continue,
+                Err(err) => match err.kind() {
+                    ErrorKind::WouldBlock => break Ok(()),
+                    ErrorKind::Interrupted => continue,
+                    _ => break Err(err),
+                }
+            }
+        }
+    }
+}
+
+impl PipeWriter {
+    /// Writes into the pipe without raising `SIGPIPE` if the read end is closed.
+    fn send(&self, buf: &[u8]) -> Result<usize, Error> {
+        let res = unsafe {
+            libc::send(self.sock.as_raw_fd(), buf.as_ptr() as *const libc::c_void, buf.len(), SEND_FLAGS)
+        };
+        if res < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(res as usize)
+    }
+
+    /// Puts a single byte into the pipe, so the read end becomes readable.
+    pub fn notify(&self) -> Result<(), Error> {
+        loop {
+            match self.send(&[0]) {
+                Ok(0) => continue,
+                Ok(_) => break Ok(()),
+                Err(err) => match err.kind() {
+                    ErrorKind::WouldBlock => break Ok(()),
+                    ErrorKind::Interrupted => continue,
+                    _ if is_closed(&err) => break Ok(()),
+                    _ => break Err(err),
+                }
+            }
+        }
+    }
+
+    /// Fills the pipe up, so the write end stops being writable.
+    pub fn fill(&self) -> Result<(), Error> {
+        let buf = [0; CHUNK_SIZE];
+        let mut len = buf.len();
+        loop {
+            match self.send(&buf[..len]) {
+                Ok(0) => break Ok(()),
+                Ok(_) => continue,
+                Err(err) => match err.kind() {
+                    ErrorKind::WouldBlock if len > 1 => len = 1,
+                    ErrorKind::WouldBlock => break Ok(()),
+                    ErrorKind::Interrupted => continue,
+                    _ if is_closed(&err) => break Ok(()),
+                    _ => break Err(err),
+                }
+            }
+        }
+    }
+
+    /// Duplicates the descriptor, so the pipe can be registered in the same poll once more.
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Ok(Self { sock: self.sock.try_clone()? })
+    }
+}
+
+/// Readiness of a single pipe, raised by one side of the FIFO and cleared by another one.
+///
+/// Also wakes the registered task and blocked threads on any progress, regardless of watermarks.
+#[derive(Debug)]
+pub struct Readiness {
+    set: AtomicBool,
+    lock: Mutex<()>,
+    waker: Mutex<Option<Waker>>,
+    registered: AtomicBool,
+    seq: AtomicUsize,
+    waiters: AtomicUsize,
+    blocked: Mutex<()>,
+    cond: Condvar,
+}
+
+impl Readiness {
+    pub fn new(set: bool) -> Self {
+        Self {
+            set: AtomicBool::new(set),
+            lock: Mutex::new(()),
+            waker: Mutex::new(None),
+            registered: AtomicBool::new(false),
+            seq: AtomicUsize::new(0),
+            waiters: AtomicUsize::new(0),
+            blocked: Mutex::new(()),
+            cond: Condvar::new(),
+        }
+    }
+
+    /// Returns the number of progress signals so far, to be passed to [`wait`](Self::wait).
+    pub fn progress_seq(&self) -> usize {
+        self.seq.load(Ordering::SeqCst)
+    }
+
+    /// Signals that the ring buffer has changed, so the blocked peer may make progress.
+    ///
+    /// Wakes the registered task, if any, and blocked threads.
+    pub fn progress(&self) {
+        self.seq.fetch_add(1, Ordering::SeqCst);
+        // Either the waiter is counted here or it sees the new sequence number before going to sleep.
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _guard = self.blocked.lock().unwrap_or_else(|e| e.into_inner());
+            self.cond.notify_all();
+        }
+        // The task retries its operation after registration, so it can't miss progress made before that.
+        if self.registered.swap(false, Ordering::SeqCst) {
+            let waker = self.waker.lock().unwrap_or_else(|e| e.into_inner()).take();
+            if let Some(w) = waker {
+                w.wake();
+            }
+        }
+    }
+
+    /// Blocks until progress is signaled after `seq` was taken or `timeout` expires.
+    ///
+    /// May return spuriously, so the caller is expected to retry its operation.
+    pub fn wait(&self, seq: usize, timeout: Option<Duration>) {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let guard = self.blocked.lock().unwrap_or_else(|e| e.into_inner());
+        if self.seq.load(Ordering::SeqCst) == seq {
+            match timeout {
+                Some(t) => drop(self.cond.wait_timeout(guard, t)),
+                None => drop(self.cond.wait(guard)),
+            }
+        }
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Raises readiness with `raise` if it isn't raised yet and `cond` holds.
+    ///
+    /// Wakes the registered task on success.
+    pub fn raise<C, F>(&self, cond: C, raise: F) -> Result<(), Error>
+    where C: Fn() -> bool, F: FnOnce() -> Result<(), Error> {
+        fence(Ordering::SeqCst);
+        if self.set.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        {
+            let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+            if self.set.load(Ordering::SeqCst) || !cond() {
+                return Ok(());
+            }
+            // The flag goes first, the peer may react to the raised pipe
+            // and try to clear it back before we return.
+            self.set.store(true, Ordering::SeqCst);
+            if let Err(err) = raise() {
+                self.set.store(false, Ordering::SeqCst);
+                return Err(err);
+            }
+        }
+        self.progress();
+        Ok(())
+    }
+
+    /// Registers a task to be woken on the next progress, replacing the previous one.
+    #[cfg(any(feature = "futures-io", feature = "tokio"))]
+    pub fn register(&self, waker: &Waker) {
+        {
+            let mut slot = self.waker.lock().unwrap_or_else(|e| e.into_inner());
+            match *slot {
+                Some(ref w) if w.will_wake(waker) => (),
+                _ => *slot = Some(waker.clone()),
+            }
+        }
+        self.registered.store(true, Ordering::SeqCst);
+    }
+
+    /// Clears readiness with `clear` if it is raised and `cond` holds.
+    ///
+    /// The flag is dropped before `cond` is checked,
+    /// so a concurrent [`raise`](Self::raise) is either seen by `cond` or waits for the lock.
+    pub fn clear<C, F>(&self, cond: C, clear: F) -> Result<(), Error>
+    where C: Fn() -> bool, F: FnOnce() -> Result<(), Error> {
+        if !self.set.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if self.set.swap(false, Ordering::SeqCst) {
+            fence(Ordering::SeqCst);
+            if cond() {
+                clear()?;
+            } else {
+                self.set.store(true, Ordering::SeqCst);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs non-blocking `op`, registers the task in readiness returned by `rd` if it would block.
+///
+/// The task is woken on any progress of the peer, not only on the transitions that raise [`Poll`](mio::Poll) readiness.
+#[cfg(any(feature = "futures-io", feature = "tokio"))]
+pub fn poll_io<S, T, R, F>(this: &mut S, cx: &mut Context, rd: R, mut op: F) -> Poll<Result<T, Error>>
+where R: Fn(&S) -> &Readiness, F: FnMut(&mut S) -> Result<T, Error> {
+    match op(this) {
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+        res => return Poll::Ready(res),
+    }
+    rd(this).register(cx.waker());
+    // The peer may have made progress before the task was registered.
+    match op(this) {
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => Poll::Pending,
+        res => Poll::Ready(res),
+    }
+}
+
+impl AsRawFd for PipeReader {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+impl AsRawFd for PipeWriter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{env, thread};
+    use std::sync::Arc;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    use mio::{Poll, Events, Token, Interest, unix::SourceFd};
+
+
+    #[test]
+    fn notify_r() {
+        let (rd, wr) = pipe().unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            wr.notify().unwrap();
+            wr
+        });
+
+        poll.registry().register(&mut SourceFd(&rd.as_raw_fd()), Token(0), Interest::READABLE).unwrap();
+
+        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
+        let mut hdl = false;
+        for e in events.iter() {
+            assert_eq!(e.token().0, 0);
+            assert!(e.is_readable());
+            hdl = true;
+        }
+        assert!(hdl);
+
+        poll.registry().deregister(&mut SourceFd(&rd.as_raw_fd())).unwrap();
+
+        jh.join().unwrap();
+    }
+
+    #[test]
+    fn drain_w() {
+        let (rd, wr) = pipe().unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+
+        wr.fill().unwrap();
+        poll.registry().register(&mut SourceFd(&wr.as_raw_fd()), Token(0), Interest::WRITABLE).unwrap();
+
+        poll.poll(&mut events, Some(Duration::from_millis(10))).unwrap();
+        assert!(events.iter().next().is_none());
+
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            rd.drain().unwrap();
+            rd
+        });
+
+        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
+        let mut hdl = false;
+        for e in events.iter() {
+            assert_eq!(e.token().0, 0);
+            assert!(e.is_writable());
+            hdl = true;
+        }
+        assert!(hdl);
+
+        poll.registry().deregister(&mut SourceFd(&wr.as_raw_fd())).unwrap();
+
+        jh.join().unwrap();
+    }
+
+    #[test]
+    fn notify_twice() {
+        let (rd, wr) = pipe().unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            wr.notify().unwrap();
+
+            thread::sleep(Duration::from_millis(10));
+            wr.notify().unwrap();
+            wr
+        });
+
+        poll.registry().register(&mut SourceFd(&rd.as_raw_fd()), Token(0), Interest::READABLE).unwrap();
+
+        for _ in 0..2 {
+            poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
+            let mut hdl = false;
+            for e in events.iter() {
+                assert_eq!(e.token().0, 0);
+                assert!(e.is_readable());
+                hdl = true;
+            }
+            assert!(hdl);
+        }
+
+        poll.registry().deregister(&mut SourceFd(&rd.as_raw_fd())).unwrap();
+
+        jh.join().unwrap();
+    }
+
+    #[test]
+    fn notify_closed() {
+        let (rd, wr) = pipe().unwrap();
+
+        let jh = thread::spawn(move || {
+            let _ = rd;
+        });
+        jh.join().unwrap();
+
+        wr.notify().unwrap();
+        wr.fill().unwrap();
+    }
+
+    #[test]
+    fn notify_closed_sigpipe() {
+        const VAR: &str = "MIO_BYTE_FIFO_SIGPIPE";
+        // The test harness ignores `SIGPIPE`, so the check runs in a child process with the default action.
+        if env::var_os(VAR).is_none() {
+            let status = Command::new(env::current_exe().unwrap())
+                .args(["--exact", "signal::test::notify_closed_sigpipe"])
+                .env(VAR, "1")
+                .stdout(Stdio::null())
+                .status().unwrap();
+            assert!(status.success(), "{}", status);
+            return;
+        }
+
+        unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+        let (rd, wr) = pipe().unwrap();
+        drop(rd);
+        wr.notify().unwrap();
+        wr.fill().unwrap();
+    }
+
+    #[test]
+    fn wait_progress() {
+        let rd = Arc::new(Readiness::new(false));
+
+        let seq = rd.progress_seq();
+        let jh = {
+            let rd = rd.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                rd.progress();
+            })
+        };
+        while rd.progress_seq() == seq {
+            rd.wait(seq, None);
+        }
+        jh.join().unwrap();
+
+        // Progress signaled before waiting isn't lost.
+        let seq = rd.progress_seq();
+        rd.progress();
+        rd.wait(seq, None);
+
+        let seq = rd.progress_seq();
+        rd.wait(seq, Some(Duration::from_millis(10)));
+        assert_eq!(rd.progress_seq(), seq);
+    }
+
+    #[test]
+    fn close_r() {
+        let (rd, wr) = pipe().unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+
+        poll.registry().register(&mut SourceFd(&rd.as_raw_fd()), Token(0), Interest::READABLE).unwrap();
+
+        let jh = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            let _ = wr;
+        });
+
+        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
+        let mut hdl = false;
+        for e in events.iter() {
+            assert_eq!(e.token().0, 0);
+            assert!(e.is_read_closed());
+            hdl = true;
+        }
+        assert!(hdl);
+
+        rd.drain().unwrap();
+
+        jh.join().unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+//! State shared between both ends of the FIFO.
+
+use std::io::Error;
+use std::sync::{Mutex, atomic::{AtomicU8, AtomicU64, Ordering}};
+
+const PRODUCER_CLOSED: u8 = 1;
+const CONSUMER_CLOSED: u8 = 2;
+const ABNORMAL: u8 = 4;
+
+/// Which ends of the FIFO are closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FifoState {
+    /// Both ends are open.
+    Open,
+    /// The producer is closed, the consumer can still read the remaining data.
+    ProducerClosed,
+    /// The consumer is closed, the producer fails to write.
+    ConsumerClosed,
+    /// Both ends are closed.
+    Both,
+}
+
+impl FifoState {
+    fn from_bits(bits: u8) -> Self {
+        match bits & (PRODUCER_CLOSED | CONSUMER_CLOSED) {
+            0 => FifoState::Open,
+            PRODUCER_CLOSED => FifoState::ProducerClosed,
+            CONSUMER_CLOSED => FifoState::ConsumerClosed,
+            _ => FifoState::Both,
+        }
+    }
+
+    /// Checks whether both ends are open.
+    pub fn is_open(self) -> bool {
+        self == FifoState::Open
+    }
+
+    /// Checks whether the producer is closed.
+    pub fn is_producer_closed(self) -> bool {
+        matches!(self, FifoState::ProducerClosed | FifoState::Both)
+    }
+
+    /// Checks whether the consumer is closed.
+    pub fn is_consumer_closed(self) -> bool {
+        matches!(self, FifoState::ConsumerClosed | FifoState::Both)
+    }
+}
+
+/// State shared between [`Producer`](crate::Producer) and [`Consumer`](crate::Consumer).
+#[derive(Debug, Default)]
+pub struct State {
+    closed: AtomicU8,
+    reason: Mutex<Option<Error>>,
+    written: AtomicU64,
+    read: AtomicU64,
+    name: Option<String>,
+}
+
+impl State {
+    pub fn with_name(name: Option<String>) -> Self {
+        Self { name, ..Self::default() }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn get(&self) -> FifoState {
+        FifoState::from_bits(self.closed.load(Ordering::SeqCst))
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.get().is_open()
+    }
+
+    pub fn close_producer(&self) {
+        self.closed.fetch_or(PRODUCER_CLOSED, Ordering::SeqCst);
+    }
+
+    pub fn close_consumer(&self) {
+        self.closed.fetch_or(CONSUMER_CLOSED, Ordering::SeqCst);
+    }
+
+    /// Marks the producer as closed abnormally.
+    pub fn set_abnormal(&self) {
+        self.closed.fetch_or(ABNORMAL, Ordering::SeqCst);
+    }
+
+    pub fn is_abnormal(&self) -> bool {
+        self.closed.load(Ordering::SeqCst) & ABNORMAL != 0
+    }
+
+    pub fn add_written(&self, count: usize) {
+        self.written.fetch_add(count as u64, Ordering::SeqCst);
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written.load(Ordering::SeqCst)
+    }
+
+    pub fn add_read(&self, count: usize) {
+        self.read.fetch_add(count as u64, Ordering::SeqCst);
+    }
+
+    pub fn read(&self) -> u64 {
+        self.read.load(Ordering::SeqCst)
+    }
+
+    /// Stores the error the producer is going to be closed with.
+    pub fn set_reason(&self, err: Error) {
+        *self.reason.lock().unwrap_or_else(|e| e.into_inner()) = Some(err);
+    }
+
+    /// Returns the error the producer was closed with.
+    ///
+    /// The original error is returned only once, later calls get errors with the same kind and message.
+    pub fn reason(&self) -> Option<Error> {
+        let mut reason = self.reason.lock().unwrap_or_else(|e| e.into_inner());
+        let err = reason.take()?;
+        *reason = Some(Error::new(err.kind(), err.to_string()));
+        Some(err)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::ErrorKind;
+
+    #[test]
+    fn transitions() {
+        let st = State::default();
+        assert_eq!(st.get(), FifoState::Open);
+        assert!(st.is_open());
+
+        st.close_consumer();
+        assert_eq!(st.get(), FifoState::ConsumerClosed);
+        assert!(st.get().is_consumer_closed());
+        assert!(!st.get().is_producer_closed());
+
+        st.close_consumer();
+        assert_eq!(st.get(), FifoState::ConsumerClosed);
+
+        st.close_producer();
+        assert_eq!(st.get(), FifoState::Both);
+        assert!(st.get().is_consumer_closed());
+        assert!(st.get().is_producer_closed());
+        assert!(!st.is_open());
+    }
+
+    #[test]
+    fn reason() {
+        let st = State::default();
+        assert!(st.reason().is_none());
+
+        st.set_reason(Error::new(ErrorKind::InvalidData, "Bad frame"));
+        for _ in 0..2 {
+            let err = st.reason().unwrap();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert_eq!(err.to_string(), "Bad frame");
+        }
+    }
+}
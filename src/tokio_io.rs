@@ -0,0 +1,142 @@
+//! [`tokio`](tokio::io) traits, enabled by `tokio` feature.
+//!
+//! The FIFO is not registered in the tokio reactor, so any runtime flavor can be used.
+
+use std::io::{Write, Read, IoSlice, Error};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{Producer, Consumer};
+use crate::signal::poll_io;
+
+impl AsyncWrite for Producer {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<Result<usize, Error>> {
+        poll_io(self.get_mut(), cx, |p| &p.rdw, |p| p.write(buf))
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context, bufs: &[IoSlice]) -> Poll<Result<usize, Error>> {
+        poll_io(self.get_mut(), cx, |p| &p.rdw, |p| p.write_vectored(bufs))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Error>> {
+        Poll::Ready(self.get_mut().flush())
+    }
+
+    /// Closes the producer, so the consumer gets end of stream after the remaining data.
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Error>> {
+        Poll::Ready(self.get_mut().close())
+    }
+}
+
+impl AsyncRead for Consumer {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<Result<(), Error>> {
+        // Nothing can be read into a full buffer, waiting for data would never end.
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        let res = poll_io(self.get_mut(), cx, |c| &c.rdr, |c| c.read(buf.initialize_unfilled()));
+        res.map_ok(|num| buf.advance(num))
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::{create, create_with, Watermarks};
+
+    #[tokio::test]
+    async fn copy() {
+        let (mut p, mut c) = create(7).unwrap();
+        let data = (0..1000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let sent = data.clone();
+
+        let jh = tokio::spawn(async move {
+            let mut src = &sent[..];
+            assert_eq!(tokio::io::copy(&mut src, &mut p).await.unwrap(), 1000);
+            p.shutdown().await.unwrap();
+        });
+
+        let mut received = Vec::new();
+        c.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, data);
+
+        jh.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn close_cons() {
+        let (mut p, c) = create(4).unwrap();
+
+        p.write_all(b"abcd").await.unwrap();
+
+        let jh = tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            drop(c);
+        });
+
+        let err = p.write_all(b"e").await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+
+        jh.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_block() {
+        let (mut p, mut c) = create(4).unwrap();
+        let mut buf = [0; 4];
+
+        let jh = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            std::io::Write::write(&mut p, b"abc").unwrap()
+        });
+
+        assert_eq!(c.read(&mut buf).await.unwrap(), 3);
+        assert_eq!(&buf[0..3], b"abc");
+        assert_eq!(jh.join().unwrap(), 3);
+        assert_eq!(c.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn empty_bufs() {
+        let (mut p, mut c) = create(4).unwrap();
+
+        assert_eq!(c.read(&mut []).await.unwrap(), 0);
+        p.write_all(b"abcd").await.unwrap();
+        assert_eq!(p.write(b"").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn watermarks() {
+        let (mut p, mut c) = create_with(16, Watermarks { low: 4, high: 16 }).unwrap();
+
+        // The read is woken before `low` bytes are buffered.
+        let jh = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            assert_eq!(std::io::Write::write(&mut p, b"ab").unwrap(), 2);
+            p
+        });
+        assert_eq!(c.read(&mut [0; 4]).await.unwrap(), 2);
+        let mut p = jh.join().unwrap();
+
+        // The write is woken before `high` bytes are freed.
+        p.write_all(&[0; 16]).await.unwrap();
+        let jh = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            assert_eq!(std::io::Read::read(&mut c, &mut [0; 2]).unwrap(), 2);
+            c
+        });
+        assert_eq!(p.write(b"cdef").await.unwrap(), 2);
+        jh.join().unwrap();
+    }
+}